@@ -45,6 +45,42 @@ fn main() -> Result<()> {
         assert_eq!(file_config.max_capacity, config.max_capacity)
     }
 
+    if command_line_args.chunk_scrub_rate.is_some() {
+        assert_eq!(command_line_args.chunk_scrub_rate, config.chunk_scrub_rate)
+    } else {
+        assert_eq!(file_config.chunk_scrub_rate, config.chunk_scrub_rate)
+    }
+
+    if command_line_args.chunk_read_strategy.is_some() {
+        assert_eq!(
+            command_line_args.chunk_read_strategy,
+            config.chunk_read_strategy
+        )
+    } else {
+        assert_eq!(file_config.chunk_read_strategy, config.chunk_read_strategy)
+    }
+
+    if command_line_args.chunk_cache_size.is_some() {
+        assert_eq!(command_line_args.chunk_cache_size, config.chunk_cache_size)
+    } else {
+        assert_eq!(file_config.chunk_cache_size, config.chunk_cache_size)
+    }
+
+    if command_line_args.encryption_key_file.is_some() {
+        assert_eq!(
+            command_line_args.encryption_key_file,
+            config.encryption_key_file
+        )
+    } else {
+        assert_eq!(file_config.encryption_key_file, config.encryption_key_file)
+    }
+
+    // The passphrase is never written to the config file.
+    assert_eq!(
+        command_line_args.encryption_passphrase,
+        config.encryption_passphrase
+    );
+
     if command_line_args.root_dir.is_some() {
         assert_eq!(command_line_args.root_dir, config.root_dir)
     } else {
@@ -69,6 +105,10 @@ fn main() -> Result<()> {
         assert_eq!(file_config.log_dir, config.log_dir)
     }
 
+    assert_eq!(
+        config.chunk_compression,
+        file_config.chunk_compression || command_line_args.chunk_compression
+    );
     assert_eq!(
         config.update,
         file_config.update || command_line_args.update
@@ -188,6 +228,9 @@ fn main() -> Result<()> {
         )
     }
 
+    // The command is never written to the config file.
+    assert_eq!(command_line_args.command, config.command);
+
     clear_disk_config()?;
 
     Ok(())
@@ -0,0 +1,376 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Export and import of a node's root directory as a portable archive, to move a node to another
+//! machine.
+//!
+//! An archive is a gzip stream of a header with the archive version, the files under the root
+//! directory, and a manifest with a SHA-256 checksum of each store, i.e. of each top-level entry of
+//! the root directory. Files are archived as they are on disk, so encrypted stores stay encrypted
+//! and the same secret has to be provided to the node after import. The node should be stopped
+//! while its root directory is exported or imported.
+
+use crate::{utils::TEMP_FILE_SUFFIX, Error, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::info;
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+const ARCHIVE_MAGIC: [u8; 8] = *b"SNARCHIV";
+/// Version of the archive format written by `export`.
+pub const ARCHIVE_VERSION: u32 = 1;
+const IMPORT_DIR_SUFFIX: &str = ".import";
+const COPY_BUFFER_LEN: usize = 64 * 1024;
+
+/// Summary of the files of a store in an archive.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreDigest {
+    /// Number of files in the store.
+    pub files: u64,
+    /// Total size of the files in bytes.
+    pub bytes: u64,
+    /// SHA-256 over the paths, sizes and contents of the files, in the order they are archived.
+    pub checksum: Vec<u8>,
+}
+
+/// The stores of an archive, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the node which wrote the archive.
+    pub node_version: String,
+    /// Digest of each store.
+    pub stores: BTreeMap<String, StoreDigest>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 8],
+    version: u32,
+}
+
+// Precedes the contents of each file in the archive. `path` is relative to the root directory,
+// with `/` separators.
+#[derive(Serialize, Deserialize)]
+struct FileHeader {
+    path: String,
+    len: u64,
+}
+
+/// Writes the contents of `root_dir` to an archive at `archive_path`, returning its manifest.
+pub fn export(root_dir: &Path, archive_path: &Path) -> Result<Manifest> {
+    if !root_dir.is_dir() {
+        return Err(Error::Archive(format!(
+            "No root directory at {}",
+            root_dir.display()
+        )));
+    }
+    let mut paths = vec![];
+    list_files(root_dir, root_dir, &mut paths)?;
+
+    let mut writer = GzEncoder::new(
+        BufWriter::new(File::create(archive_path)?),
+        Compression::default(),
+    );
+    bincode::serialize_into(
+        &mut writer,
+        &Header {
+            magic: ARCHIVE_MAGIC,
+            version: ARCHIVE_VERSION,
+        },
+    )?;
+
+    let mut digests = Digests::default();
+    for path in paths {
+        let mut file = File::open(root_dir.join(&path))?;
+        let len = file.metadata()?.len();
+        let context = digests.start_file(&path, len);
+        bincode::serialize_into(&mut writer, &Some(FileHeader { path, len }))?;
+        copy(&mut file, &mut writer, len, context)?;
+    }
+    bincode::serialize_into(&mut writer, &None::<FileHeader>)?;
+
+    let manifest = digests.into_manifest();
+    bincode::serialize_into(&mut writer, &manifest)?;
+    writer
+        .finish()?
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    info!(
+        "Exported {} stores from {} to {}",
+        manifest.stores.len(),
+        root_dir.display(),
+        archive_path.display()
+    );
+    Ok(manifest)
+}
+
+/// Restores the contents of the archive at `archive_path` to `root_dir`, returning its manifest.
+///
+/// The archive is extracted next to `root_dir` and only moved into place once all its stores
+/// match the manifest. An existing non-empty `root_dir` is replaced only if `force` is set.
+pub fn import(archive_path: &Path, root_dir: &Path, force: bool) -> Result<Manifest> {
+    let root_dir_in_use = root_dir.is_dir() && fs::read_dir(root_dir)?.next().is_some();
+    if root_dir_in_use && !force {
+        return Err(Error::Archive(format!(
+            "Root directory {} is not empty, import with --force to replace it",
+            root_dir.display()
+        )));
+    }
+
+    let mut import_dir = root_dir.as_os_str().to_owned();
+    import_dir.push(IMPORT_DIR_SUFFIX);
+    let import_dir = PathBuf::from(import_dir);
+    if import_dir.exists() {
+        fs::remove_dir_all(&import_dir)?;
+    }
+    fs::create_dir_all(&import_dir)?;
+
+    let manifest = match extract(archive_path, &import_dir) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            fs::remove_dir_all(&import_dir)?;
+            return Err(error);
+        }
+    };
+
+    if root_dir.exists() {
+        fs::remove_dir_all(root_dir)?;
+    }
+    fs::rename(&import_dir, root_dir)?;
+    info!(
+        "Imported {} stores from {} to {}",
+        manifest.stores.len(),
+        archive_path.display(),
+        root_dir.display()
+    );
+    Ok(manifest)
+}
+
+// Extracts the archive at `archive_path` to `dir`, checking it against its manifest.
+fn extract(archive_path: &Path, dir: &Path) -> Result<Manifest> {
+    let mut reader = GzDecoder::new(BufReader::new(File::open(archive_path)?));
+    let header: Header = bincode::deserialize_from(&mut reader)?;
+    if header.magic != ARCHIVE_MAGIC {
+        return Err(Error::Archive(format!(
+            "{} is not a node archive",
+            archive_path.display()
+        )));
+    }
+    if header.version != ARCHIVE_VERSION {
+        return Err(Error::Archive(format!(
+            "Unsupported archive version {}, expected {}",
+            header.version, ARCHIVE_VERSION
+        )));
+    }
+
+    let mut digests = Digests::default();
+    while let Some(FileHeader { path, len }) = bincode::deserialize_from(&mut reader)? {
+        let file_path = dir.join(relative_path(&path)?);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let context = digests.start_file(&path, len);
+        let mut file = File::create(&file_path)?;
+        copy(&mut reader, &mut file, len, context)?;
+        file.sync_all()?;
+    }
+
+    let manifest: Manifest = bincode::deserialize_from(&mut reader)?;
+    let extracted = digests.into_manifest();
+    for (name, digest) in &manifest.stores {
+        if extracted.stores.get(name) != Some(digest) {
+            return Err(Error::Archive(format!(
+                "Store {} does not match the archive manifest",
+                name
+            )));
+        }
+    }
+    if let Some(name) = extracted
+        .stores
+        .keys()
+        .find(|name| !manifest.stores.contains_key(*name))
+    {
+        return Err(Error::Archive(format!(
+            "Store {} is missing from the archive manifest",
+            name
+        )));
+    }
+    Ok(manifest)
+}
+
+// Per-store digests of the files archived so far.
+#[derive(Default)]
+struct Digests {
+    stores: BTreeMap<String, (u64, u64, Context)>,
+}
+
+impl Digests {
+    // Accounts for a file of `len` bytes at `path`, returning the context to hash its contents with.
+    fn start_file(&mut self, path: &str, len: u64) -> &mut Context {
+        let store = path.split('/').next().unwrap_or_default().to_string();
+        let (files, bytes, context) = self
+            .stores
+            .entry(store)
+            .or_insert_with(|| (0, 0, Context::new(&SHA256)));
+        *files += 1;
+        *bytes += len;
+        context.update(path.as_bytes());
+        context.update(&len.to_le_bytes());
+        context
+    }
+
+    fn into_manifest(self) -> Manifest {
+        let stores = self
+            .stores
+            .into_iter()
+            .map(|(name, (files, bytes, context))| {
+                let checksum = context.finish().as_ref().to_vec();
+                (
+                    name,
+                    StoreDigest {
+                        files,
+                        bytes,
+                        checksum,
+                    },
+                )
+            })
+            .collect();
+        Manifest {
+            node_version: env!("CARGO_PKG_VERSION").to_string(),
+            stores,
+        }
+    }
+}
+
+// Copies exactly `len` bytes from `reader` to `writer`, hashing them into `context`.
+fn copy<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    len: u64,
+    context: &mut Context,
+) -> Result<()> {
+    let mut buffer = vec![0; COPY_BUFFER_LEN];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk_len = remaining.min(COPY_BUFFER_LEN as u64) as usize;
+        reader.read_exact(&mut buffer[..chunk_len])?;
+        context.update(&buffer[..chunk_len]);
+        writer.write_all(&buffer[..chunk_len])?;
+        remaining -= chunk_len as u64;
+    }
+    Ok(())
+}
+
+// Collects the paths of the files under `dir`, relative to `root_dir` and sorted, skipping the
+// temp files of interrupted writes.
+fn list_files(root_dir: &Path, dir: &Path, paths: &mut Vec<String>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            list_files(root_dir, &path, paths)?;
+            continue;
+        }
+        let name = entry.file_name();
+        if name.to_string_lossy().ends_with(TEMP_FILE_SUFFIX) {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root_dir)
+            .map_err(|e| Error::Logic(e.to_string()))?;
+        let components = relative
+            .components()
+            .map(|component| {
+                component.as_os_str().to_str().ok_or_else(|| {
+                    Error::Archive(format!("Non UTF-8 file name {}", relative.display()))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        paths.push(components.join("/"));
+    }
+    Ok(())
+}
+
+// Converts an archived path to a path relative to the root directory, refusing any that would
+// escape it.
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let relative: PathBuf = path.split('/').collect();
+    let is_plain = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if path.is_empty() || !is_plain {
+        return Err(Error::Archive(format!("Invalid path in archive: {}", path)));
+    }
+    Ok(relative)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{export, import};
+    use crate::{Error, Result};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn export_and_import() -> Result<()> {
+        let temp_dir =
+            TempDir::new("archive").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let root_dir = temp_dir.path().join("root_dir");
+        let archive_path = temp_dir.path().join("node.archive");
+        fs::create_dir_all(root_dir.join("chunks").join("immutable"))?;
+        fs::write(root_dir.join("network_keypair"), b"keypair")?;
+        fs::write(
+            root_dir.join("chunks").join("immutable").join("ab"),
+            b"chunk",
+        )?;
+        fs::write(
+            root_dir.join("chunks").join("immutable").join("cd.tmp"),
+            b"torn",
+        )?;
+
+        let manifest = export(&root_dir, &archive_path)?;
+        assert_eq!(manifest.stores.len(), 2);
+        assert_eq!(manifest.stores["chunks"].files, 1);
+
+        // An existing root dir is only replaced when forced.
+        assert!(import(&archive_path, &root_dir, false).is_err());
+        fs::write(root_dir.join("network_keypair"), b"other keypair")?;
+        assert_eq!(import(&archive_path, &root_dir, true)?, manifest);
+        assert_eq!(fs::read(root_dir.join("network_keypair"))?, b"keypair");
+        assert!(!root_dir
+            .join("chunks")
+            .join("immutable")
+            .join("cd.tmp")
+            .exists());
+
+        let new_root_dir = temp_dir.path().join("new_root_dir");
+        let _ = import(&archive_path, &new_root_dir, false)?;
+        assert_eq!(
+            fs::read(new_root_dir.join("chunks").join("immutable").join("ab"))?,
+            b"chunk"
+        );
+
+        // A corrupt archive is refused and leaves no trace.
+        let mut contents = fs::read(&archive_path)?;
+        let middle = contents.len() / 2;
+        contents[middle] ^= 0xff;
+        fs::write(&archive_path, contents)?;
+        let other_root_dir = temp_dir.path().join("other_root_dir");
+        assert!(import(&archive_path, &other_root_dir, false).is_err());
+        assert!(!other_root_dir.exists());
+        Ok(())
+    }
+}
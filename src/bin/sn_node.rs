@@ -29,7 +29,9 @@
 
 use log::{self, error, info};
 use self_update::{cargo_crate_version, Status};
-use sn_node::{self, add_connection_info, set_connection_info, utils, Config, Node};
+use sn_node::{
+    self, add_connection_info, archive, set_connection_info, utils, Command, Config, Node,
+};
 use std::{io::Write, process};
 use structopt::{clap, StructOpt};
 
@@ -82,6 +84,10 @@ async fn run_node() {
 
     utils::init_logging(&config);
 
+    if let Some(command) = config.command() {
+        return run_command(&config, command);
+    }
+
     if config.update() || config.update_only() {
         match update() {
             Ok(status) => {
@@ -177,6 +183,27 @@ async fn run_node() {
     }
 }
 
+/// Runs a command on the node's root directory, and exits.
+fn run_command(config: &Config, command: &Command) {
+    let result = config.root_dir().and_then(|root_dir| match command {
+        Command::Export { to } => archive::export(&root_dir, to),
+        Command::Import { from, force } => archive::import(from, &root_dir, *force),
+    });
+    match result {
+        Ok(manifest) => {
+            for (store, digest) in manifest.stores {
+                println!("{}: {} files, {} bytes", store, digest.files, digest.bytes);
+            }
+            exit(0);
+        }
+        Err(e) => {
+            println!("Cannot run command due to error: {:?}", e);
+            error!("Cannot run command due to error: {:?}", e);
+            exit(1);
+        }
+    }
+}
+
 fn exit(exit_code: i32) {
     log::logger().flush();
     process::exit(exit_code);
@@ -0,0 +1,146 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! sn_node_store inspects and repairs the chunk and transfer stores of a stopped node, without
+//! connecting to the network.
+
+#![doc(
+    html_logo_url = "https://raw.githubusercontent.com/maidsafe/QA/master/Images/maidsafe_logo.png",
+    html_favicon_url = "https://maidsafe.net/img/favicon.ico",
+    test(attr(forbid(warnings)))
+)]
+// For explanation of lint checks, run `rustc -W help`.
+#![forbid(unsafe_code)]
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+
+use sn_node::{
+    inspect::{ChunkKind, StoreInspector},
+    Config, Result,
+};
+use std::{path::PathBuf, process};
+use structopt::StructOpt;
+
+/// Inspect and repair the stores of a node while it is stopped.
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Root directory of the node's stores. Defaults to the node's default root directory.
+    #[structopt(long, parse(from_os_str))]
+    root_dir: Option<PathBuf>,
+    /// File holding the secret the stores were encrypted with, if they are encrypted.
+    #[structopt(long, parse(from_os_str))]
+    encryption_key_file: Option<PathBuf>,
+    /// Passphrase the stores were encrypted with, as an alternative to `--encryption-key-file`.
+    #[structopt(long, env = "SN_NODE_ENCRYPTION_PASSPHRASE", hide_env_values = true)]
+    encryption_passphrase: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// List the keys of the chunks of a type: immutable, mutable, sequence or register.
+    Chunks { kind: ChunkKind },
+    /// Decode and print the chunk of a type stored under a key.
+    Chunk { kind: ChunkKind, key: String },
+    /// Verify the chunks and used space of a type, or of all types if none is given.
+    Verify { kind: Option<ChunkKind> },
+    /// List the wallets with transfer events stored.
+    Wallets,
+    /// Print the transfer events stored for a wallet, as named by `wallets`.
+    History { wallet: String },
+    /// Remove the temp files left behind by writes interrupted by a crash.
+    Prune,
+}
+
+#[tokio::main]
+async fn main() {
+    match run(Args::from_args()).await {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            eprintln!("sn_node_store: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the command, returning whether the stores checked out.
+async fn run(args: Args) -> Result<bool> {
+    let mut config = Config::default();
+    if let Some(root_dir) = args.root_dir {
+        config.set_root_dir(root_dir);
+    }
+    config.encryption_key_file = args.encryption_key_file;
+    config.encryption_passphrase = args.encryption_passphrase;
+    let secret = config.encryption_secret()?;
+    let inspector = StoreInspector::new(&config.root_dir()?, secret.as_deref())?;
+
+    match args.command {
+        Command::Chunks { kind } => {
+            for key in inspector.chunk_keys(kind).await? {
+                println!("{}", key);
+            }
+        }
+        Command::Chunk { kind, key } => println!("{}", inspector.chunk(kind, &key).await?),
+        Command::Verify { kind } => {
+            let explicit = kind.is_some();
+            let kinds = kind.map_or_else(|| ChunkKind::ALL.to_vec(), |kind| vec![kind]);
+            let mut all_ok = true;
+            for kind in kinds {
+                let verification = match inspector.verify(kind).await {
+                    Ok(verification) => verification,
+                    // Only explicitly asked for stores have to exist.
+                    Err(error) if explicit => return Err(error),
+                    Err(_) => continue,
+                };
+                println!(
+                    "{}: {} chunks, {} bytes stored, {} bytes recorded as used",
+                    kind,
+                    verification.chunks,
+                    verification.stored_size,
+                    verification
+                        .recorded_used_space
+                        .map_or_else(|| "no".to_string(), |used| used.to_string()),
+                );
+                for key in &verification.corrupt {
+                    println!("{}: corrupt chunk {}", kind, key);
+                }
+                all_ok &= verification.is_ok();
+            }
+            return Ok(all_ok);
+        }
+        Command::Wallets => {
+            for wallet in inspector.wallets()? {
+                match wallet.key {
+                    Some(key) => println!("{} {}", wallet.name, key),
+                    None => println!("{} (empty)", wallet.name),
+                }
+            }
+        }
+        Command::History { wallet } => {
+            for event in inspector.wallet_history(&wallet)? {
+                println!("{:#?}", event);
+            }
+        }
+        Command::Prune => {
+            for path in inspector.prune_temp_files().await? {
+                println!("Removed {}", path.display());
+            }
+        }
+    }
+    Ok(true)
+}
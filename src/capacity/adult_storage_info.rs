@@ -6,20 +6,230 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::{CHUNK_COPY_COUNT, FULL_ADULT_LEASE};
+use itertools::Itertools;
 use sn_routing::XorName;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
+/// The info on the storage of the Adults of a section.
+///
+/// Adults report their storage full to both their Elders and the other Adults, so that both keep
+/// the same info, and select the same holders for a chunk with `chunk_holders`.
+///
+/// An Adult joining learns of the Adults already full from their next report, within
+/// `chunks::STORAGE_FULL_RENEW_INTERVAL`. Elders can't push the full adults to it instead, as
+/// sn_messaging has no message for that.
 #[derive(Clone, Default)]
 pub struct AdultsStorageInfo {
-    pub full_adults: Arc<RwLock<BTreeSet<XorName>>>,
+    /// When each adult last reported its storage full. It is only counted as full for
+    /// `FULL_ADULT_LEASE` after that, as it keeps reporting it for as long as it is.
+    full_adults: Arc<RwLock<BTreeMap<XorName, Instant>>>,
 }
 
 impl AdultsStorageInfo {
     ///
     pub fn new() -> Self {
-        let full_adults = Arc::new(RwLock::new(BTreeSet::new()));
+        let full_adults = Arc::new(RwLock::new(BTreeMap::new()));
         Self { full_adults }
     }
+
+    /// Returns the adults counted as full, i.e. that reported their storage full less than
+    /// `FULL_ADULT_LEASE` ago.
+    pub async fn full_adults(&self) -> BTreeSet<XorName> {
+        self.full_adults_at(Instant::now()).await
+    }
+
+    async fn full_adults_at(&self, now: Instant) -> BTreeSet<XorName> {
+        self.full_adults
+            .read()
+            .await
+            .iter()
+            .filter(|(_, reported)| now.saturating_duration_since(**reported) < FULL_ADULT_LEASE)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Counts `full_adults` as full for another `FULL_ADULT_LEASE`, dropping those whose lease
+    /// expired. Returns those not counted as full before.
+    pub async fn insert_full_adults(&self, full_adults: BTreeSet<XorName>) -> BTreeSet<XorName> {
+        let now = Instant::now();
+        let mut reported = self.full_adults.write().await;
+        reported.retain(|_, at| now.saturating_duration_since(*at) < FULL_ADULT_LEASE);
+        full_adults
+            .into_iter()
+            .filter(|name| reported.insert(*name, now).is_none())
+            .collect()
+    }
+
+    /// Stops tracking the adults not among `members`.
+    pub async fn retain_members_only(&self, members: &BTreeSet<XorName>) {
+        self.full_adults
+            .write()
+            .await
+            .retain(|name, _| members.contains(name));
+    }
+
+    /// Returns the holders of the chunk named `target` among `adults`: the `CHUNK_COPY_COUNT`
+    /// closest to it that are not full.
+    pub async fn chunk_holders(
+        &self,
+        target: &XorName,
+        adults: &BTreeSet<XorName>,
+    ) -> BTreeSet<XorName> {
+        let full_adults = self.full_adults().await;
+        adults
+            .iter()
+            .filter(|name| !full_adults.contains(name))
+            .sorted_by(|lhs, rhs| target.cmp_distance(lhs, rhs))
+            .take(CHUNK_COPY_COUNT)
+            .copied()
+            .collect()
+    }
+
+    /// Returns the adults among `adults` that could have been holders of the chunk named `target`
+    /// before churn displaced them by its current `holders`: the ones closest to it that are not
+    /// among those, up to `CHUNK_COPY_COUNT` of them not full, as there are as many holders, and
+    /// with those full in between, as holders are displaced for getting full too.
+    pub async fn displaced_holders(
+        &self,
+        target: &XorName,
+        adults: &BTreeSet<XorName>,
+        holders: &BTreeSet<XorName>,
+    ) -> BTreeSet<XorName> {
+        let full_adults = self.full_adults().await;
+        let mut candidates = 0;
+        adults
+            .difference(holders)
+            .sorted_by(|lhs, rhs| target.cmp_distance(lhs, rhs))
+            .take_while(|name| {
+                if full_adults.contains(name) {
+                    return true;
+                }
+                candidates += 1;
+                candidates <= CHUNK_COPY_COUNT
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Returns up to `count` adults among `adults` to store the chunk named `target` in place of
+    /// holders that failed to: the ones closest to it that are not full nor `excluded`.
+    pub async fn alternate_holders(
+        &self,
+        target: &XorName,
+        adults: &BTreeSet<XorName>,
+        excluded: &BTreeSet<XorName>,
+        count: usize,
+    ) -> BTreeSet<XorName> {
+        let full_adults = self.full_adults().await;
+        adults
+            .iter()
+            .filter(|name| !full_adults.contains(name) && !excluded.contains(name))
+            .sorted_by(|lhs, rhs| target.cmp_distance(lhs, rhs))
+            .take(count)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AdultsStorageInfo;
+    use crate::capacity::{CHUNK_COPY_COUNT, FULL_ADULT_LEASE};
+    use itertools::Itertools;
+    use sn_routing::XorName;
+    use std::collections::BTreeSet;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn adults_select_same_holders_as_elders() {
+        let adults: BTreeSet<_> = (0..12).map(|_| XorName::random()).collect();
+        let full_adults: BTreeSet<_> = adults.iter().take(3).copied().collect();
+
+        // Both the Elders and the Adults are sent the same reports.
+        let elders_view = AdultsStorageInfo::new();
+        let adults_view = AdultsStorageInfo::new();
+        for view in &[&elders_view, &adults_view] {
+            let _ = view.insert_full_adults(full_adults.clone()).await;
+        }
+
+        for _ in 0..100 {
+            let target = XorName::random();
+            let holders = elders_view.chunk_holders(&target, &adults).await;
+            assert_eq!(adults_view.chunk_holders(&target, &adults).await, holders);
+            assert_eq!(holders.len(), CHUNK_COPY_COUNT);
+            assert!(holders.is_disjoint(&full_adults));
+            // The closest adults not full.
+            let closest: BTreeSet<_> = adults
+                .difference(&full_adults)
+                .sorted_by(|lhs, rhs| target.cmp_distance(lhs, rhs))
+                .take(CHUNK_COPY_COUNT)
+                .copied()
+                .collect();
+            assert_eq!(holders, closest);
+        }
+
+        // Without the reports, an Adult would disagree.
+        let stale_view = AdultsStorageInfo::new();
+        let mut disagreeing = 0;
+        for _ in 0..100 {
+            let target = XorName::random();
+            if stale_view.chunk_holders(&target, &adults).await
+                != elders_view.chunk_holders(&target, &adults).await
+            {
+                disagreeing += 1;
+            }
+        }
+        assert!(disagreeing > 0);
+    }
+
+    #[tokio::test]
+    async fn full_adults_are_counted_full_until_their_lease_expires() {
+        let storage_info = AdultsStorageInfo::new();
+        let adults: BTreeSet<_> = (0..2).map(|_| XorName::random()).collect();
+        assert_eq!(
+            storage_info.insert_full_adults(adults.clone()).await,
+            adults
+        );
+        // Renewing the lease of an adult doesn't count it as newly full.
+        assert!(storage_info
+            .insert_full_adults(adults.clone())
+            .await
+            .is_empty());
+        assert_eq!(storage_info.full_adults().await, adults);
+
+        let expired = Instant::now() + FULL_ADULT_LEASE;
+        assert!(storage_info.full_adults_at(expired).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn displaced_holders_include_all_holders_displaced_at_once() {
+        let storage_info = AdultsStorageInfo::new();
+        for _ in 0..100 {
+            let target = XorName::random();
+            let remaining: BTreeSet<_> = (0..8).map(|_| XorName::random()).collect();
+            let old_holders = storage_info.chunk_holders(&target, &remaining).await;
+            // Two adults join closer to the chunk than any of its holders.
+            let joined: BTreeSet<_> = (0..2)
+                .map(|_| {
+                    let mut name = target;
+                    name.0[31] ^= rand::random::<u8>() | 1;
+                    name
+                })
+                .collect();
+            let adults: BTreeSet<_> = remaining.union(&joined).copied().collect();
+            let new_holders = storage_info.chunk_holders(&target, &adults).await;
+            let displaced: BTreeSet<_> = old_holders.difference(&new_holders).copied().collect();
+            assert_eq!(displaced.len(), joined.len());
+
+            let acked = storage_info
+                .displaced_holders(&target, &adults, &new_holders)
+                .await;
+            assert!(acked.is_superset(&displaced));
+            assert!(acked.is_disjoint(&new_holders));
+        }
+    }
 }
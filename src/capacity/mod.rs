@@ -9,7 +9,7 @@
 mod adult_storage_info;
 mod store_cost;
 
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, time::Duration};
 
 pub use adult_storage_info::AdultsStorageInfo;
 use sn_routing::{Prefix, XorName};
@@ -21,6 +21,10 @@ pub const MAX_SUPPLY: u64 = u32::MAX as u64 * 1_000_000_000_u64;
 // The number of separate copies of a blob chunk which should be maintained.
 pub(crate) const CHUNK_COPY_COUNT: usize = 4;
 pub(crate) const MAX_CHUNK_SIZE: u64 = 1_000_000;
+/// Time an adult is counted as full for after reporting its storage full. Adults keep reporting it
+/// while full, see `chunks::STORAGE_FULL_RENEW_INTERVAL`, so that they are no longer counted as
+/// full once they stop, e.g. after deletions or a capacity increase.
+pub const FULL_ADULT_LEASE: Duration = Duration::from_secs(5 * 60);
 
 /// A util for sharing the
 /// info on data capacity among the
@@ -63,12 +67,23 @@ impl Capacity {
         self.reader.get_chunk_holder_adults(target).await
     }
 
-    pub async fn insert_full_adults(&self, full_adults: BTreeSet<XorName>) {
-        self.writer.insert_full_adults(full_adults).await
+    // Returns `XorName`s of up to `count` adults to store a Blob chunk
+    // in place of holders that failed to, excluding those already sent it.
+    pub async fn get_alternate_holder_adults(
+        &self,
+        target: &XorName,
+        excluded: &BTreeSet<XorName>,
+        count: usize,
+    ) -> BTreeSet<XorName> {
+        self.reader
+            .get_alternate_holder_adults(target, excluded, count)
+            .await
     }
 
-    pub async fn remove_full_adults(&self, full_adults: BTreeSet<XorName>) {
-        self.writer.remove_full_adults(full_adults).await
+    /// Counts `full_adults` as full for another `FULL_ADULT_LEASE`,
+    /// returning those not counted as full before.
+    pub async fn insert_full_adults(&self, full_adults: BTreeSet<XorName>) -> BTreeSet<XorName> {
+        self.writer.insert_full_adults(full_adults).await
     }
 
     /// Registered holders not present in provided list of members
@@ -106,36 +121,43 @@ impl CapacityReader {
 
     /// Whether the adult is recorded as full
     pub async fn is_full(&self, adult: &XorName) -> bool {
-        self.adult_storage_info
-            .full_adults
-            .read()
-            .await
-            .contains(adult)
+        self.adult_storage_info.full_adults().await.contains(adult)
     }
 
     /// Number of full chunk storing nodes in the section.
     pub async fn full_adults_count(&self) -> u8 {
-        self.adult_storage_info.full_adults.read().await.len() as u8
+        self.adult_storage_info.full_adults().await.len() as u8
     }
 
     /// Number of full chunk storing nodes in the section.
     pub async fn full_adults_matching(&self, prefix: Prefix) -> BTreeSet<XorName> {
         self.adult_storage_info
-            .full_adults
-            .read()
+            .full_adults()
             .await
-            .iter()
+            .into_iter()
             .filter(|name| prefix.matches(name))
-            .copied()
             .collect()
     }
 
     // Returns `XorName`s of the target holders for an Blob chunk.
     // Used to fetch the list of holders for a new chunk.
+    // Selected the same way as by the Adults, see `AdultsStorageInfo::chunk_holders`.
     pub async fn get_chunk_holder_adults(&self, target: &XorName) -> BTreeSet<XorName> {
-        let full_adults = self.adult_storage_info.full_adults.read().await;
-        self.reader
-            .non_full_adults_closest_to(&target, &full_adults, CHUNK_COPY_COUNT)
+        let adults = self.reader.our_adults().await;
+        self.adult_storage_info.chunk_holders(target, &adults).await
+    }
+
+    // Returns `XorName`s of up to `count` adults to store a Blob chunk
+    // in place of holders that failed to, excluding those already sent it.
+    pub async fn get_alternate_holder_adults(
+        &self,
+        target: &XorName,
+        excluded: &BTreeSet<XorName>,
+        count: usize,
+    ) -> BTreeSet<XorName> {
+        let adults = self.reader.our_adults().await;
+        self.adult_storage_info
+            .alternate_holders(target, &adults, excluded, count)
             .await
     }
 }
@@ -149,35 +171,15 @@ impl CapacityWriter {
         }
     }
 
-    pub async fn insert_full_adults(&self, full_adults: BTreeSet<XorName>) {
-        let mut orig_full_adults = self.adult_storage_info.full_adults.write().await;
-
-        for adult in full_adults {
-            let _ = orig_full_adults.insert(adult);
-        }
-    }
-
-    pub async fn remove_full_adults(&self, full_adults: BTreeSet<XorName>) {
-        let mut orig_full_adults = self.adult_storage_info.full_adults.write().await;
-
-        for adult in full_adults {
-            let _ = orig_full_adults.remove(&adult);
-        }
+    pub async fn insert_full_adults(&self, full_adults: BTreeSet<XorName>) -> BTreeSet<XorName> {
+        self.adult_storage_info
+            .insert_full_adults(full_adults)
+            .await
     }
 
     /// Registered holders not present in provided list of members
     /// will be removed from adult_storage_info and no longer tracked for liveness.
     pub async fn retain_members_only(&mut self, members: &BTreeSet<XorName>) {
-        // full adults
-        let mut full_adults = self.adult_storage_info.full_adults.write().await;
-        let absent_adults = full_adults
-            .iter()
-            .filter(|&key| !members.contains(key))
-            .cloned()
-            .collect::<Vec<_>>();
-
-        for adult in &absent_adults {
-            let _ = full_adults.remove(adult);
-        }
+        self.adult_storage_info.retain_members_only(members).await
     }
 }
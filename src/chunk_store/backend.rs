@@ -0,0 +1,393 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Storage backends a `ChunkStore` can keep its serialised chunks in.
+
+use super::chunk::ChunkId;
+use crate::{
+    encryption::{self, EncryptionKey},
+    utils, Error, Result,
+};
+use bytes::Bytes;
+use log::info;
+use std::{
+    collections::BTreeMap,
+    fs::{self, DirEntry, File, Metadata},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The max name length for a chunk file.
+const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
+
+/// Directory chunk files failing an integrity check are moved to.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Number of nested prefix subdirectories a chunk file is stored under. Each level is named after
+/// one byte of the chunk's `XorName`, so a store is spread over up to 65536 leaf directories.
+const SHARD_LEVELS: usize = 2;
+
+/// Key-value storage of serialised chunks, keyed by chunk id.
+///
+/// Space accounting is left to the `ChunkStore`, a backend only stores and retrieves bytes.
+pub(crate) trait StorageBackend: Send + Sync {
+    /// The directory this backend persists to, if any. The `ChunkStore`'s used space record is
+    /// kept there as well.
+    fn dir(&self) -> Option<&Path>;
+
+    /// Tests if a value has been stored under `id`.
+    fn has<I: ChunkId>(&self, id: &I) -> bool;
+
+    /// Returns the size in bytes of the value stored under `id`, if there is one.
+    fn size<I: ChunkId>(&self, id: &I) -> Option<u64>;
+
+    /// Returns the value stored under `id`, or `None` if there is none.
+    fn get<I: ChunkId>(&self, id: &I) -> Result<Option<Vec<u8>>>;
+
+    /// Stores `value` under `id`, overwriting any previous value.
+    fn put<I: ChunkId>(&mut self, id: &I, value: &[u8]) -> Result<()>;
+
+    /// Removes the value stored under `id`. Does nothing if there is none.
+    fn delete<I: ChunkId>(&mut self, id: &I) -> Result<()>;
+
+    /// Lists the ids of all stored values.
+    fn keys<I: ChunkId>(&self) -> Vec<I>;
+
+    /// Takes the value stored under `id` out of the store, keeping it aside for inspection if the
+    /// backend supports that. Does nothing if there is none.
+    fn quarantine<I: ChunkId>(&mut self, id: &I) -> Result<()>;
+
+    /// Cleans up whatever an interrupted `put` left behind, and returns the total size in bytes of
+    /// the values stored.
+    fn recover(&mut self) -> Result<u64>;
+}
+
+/// Stores each chunk as its own file, sharded into `SHARD_LEVELS` levels of subdirectories named
+/// after the leading bytes of the chunk's `XorName`, e.g. `ab/cd/<hex encoded id>`.
+pub(crate) struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    /// Opens the chunk files at `dir`, creating the directory if it doesn't exist yet.
+    ///
+    /// Chunk files of type `I` left in the flat layout (all chunk files directly in `dir`) are
+    /// migrated to the sharded layout on opening.
+    pub(crate) fn open<I: ChunkId>(dir: PathBuf) -> Result<Self> {
+        if fs::read(&dir).is_err() {
+            Self::create_new_root(&dir)?
+        }
+        Self::migrate_flat_layout::<I>(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Opens the chunk files at `dir` as they are, without creating or migrating anything.
+    pub(crate) fn existing(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Returns the total size in bytes of the chunk files, excluding any temp files.
+    pub(crate) fn stored_size(&self) -> u64 {
+        let mut entries = Vec::new();
+        list_chunk_files(&self.dir, SHARD_LEVELS, &mut entries);
+        entries
+            .iter()
+            .filter(|entry| !is_temp_file(entry))
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// Lists the temp files left behind by a `put` interrupted by a crash.
+    pub(crate) fn temp_files(&self) -> Vec<PathBuf> {
+        let mut entries = Vec::new();
+        list_chunk_files(&self.dir, SHARD_LEVELS, &mut entries);
+        entries
+            .iter()
+            .filter(|entry| is_temp_file(entry))
+            .map(DirEntry::path)
+            .collect()
+    }
+
+    fn create_new_root(root: &Path) -> Result<()> {
+        fs::create_dir_all(root)?;
+
+        // Verify that chunk files can be created.
+        let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
+        let _ = File::create(&temp_file_path)?;
+        fs::remove_file(temp_file_path)?;
+
+        Ok(())
+    }
+
+    /// Moves any chunk files found directly in `dir` into their sharded subdirectory.
+    ///
+    /// Renaming keeps the file sizes unchanged, so the `UsedSpace` record remains valid.
+    pub(crate) fn migrate_flat_layout<I: ChunkId>(dir: &Path) -> Result<()> {
+        let entries = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .map(|kind| kind.is_file())
+                    .unwrap_or(false)
+            })
+            .filter_map(|entry| Some((to_chunk_id::<I>(&entry)?, entry.path())))
+            .collect::<Vec<_>>();
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Migrating {} chunks at {} to the sharded layout",
+            entries.len(),
+            dir.display()
+        );
+        for (id, old_path) in entries {
+            let new_path = Self::chunk_path(dir, &id)?;
+            if let Some(shard_dir) = new_path.parent() {
+                fs::create_dir_all(shard_dir)?;
+            }
+            fs::rename(old_path, new_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts in place the chunk files at `dir` stored unencrypted, quarantined ones included.
+    ///
+    /// Encrypting changes the file sizes, so the `UsedSpace` record is only valid again once rebuilt
+    /// from the stored files on opening.
+    pub(crate) fn encrypt_plaintext(dir: &Path, key: &EncryptionKey) -> Result<()> {
+        let mut entries = Vec::new();
+        list_chunk_files(dir, SHARD_LEVELS, &mut entries);
+        list_chunk_files(&dir.join(QUARANTINE_DIR), 0, &mut entries);
+
+        let mut count = 0;
+        for entry in entries.iter().filter(|entry| !is_temp_file(entry)) {
+            if encryption::encrypt_file(&entry.path(), key)? {
+                count += 1;
+            }
+        }
+        if count > 0 {
+            info!("Encrypted {} chunks at {}", count, dir.display());
+        }
+        Ok(())
+    }
+
+    pub(super) fn file_path<I: ChunkId>(&self, id: &I) -> Result<PathBuf> {
+        Self::chunk_path(&self.dir, id)
+    }
+
+    fn chunk_path<I: ChunkId>(dir: &Path, id: &I) -> Result<PathBuf> {
+        let shard_dir = id
+            .name()
+            .0
+            .iter()
+            .take(SHARD_LEVELS)
+            .fold(dir.to_path_buf(), |path, byte| {
+                path.join(format!("{:02x}", byte))
+            });
+        Ok(shard_dir.join(&hex::encode(utils::serialise(id)?)))
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn dir(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+
+    fn has<I: ChunkId>(&self, id: &I) -> bool {
+        if let Ok(path) = self.file_path(id) {
+            fs::metadata(path)
+                .as_ref()
+                .map(Metadata::is_file)
+                .unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
+    fn size<I: ChunkId>(&self, id: &I) -> Option<u64> {
+        let metadata = fs::metadata(self.file_path(id).ok()?).ok()?;
+        Some(metadata.len())
+    }
+
+    fn get<I: ChunkId>(&self, id: &I) -> Result<Option<Vec<u8>>> {
+        let mut file = match File::open(self.file_path(id)?) {
+            Ok(file) => file,
+            Err(_) => return Ok(None),
+        };
+        let mut contents = vec![];
+        let _ = file.read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    /// Writes the value to a temp file next to its final location, and only renames it into place
+    /// once it has been synced to disk, so a chunk file is never seen partially written.
+    fn put<I: ChunkId>(&mut self, id: &I, value: &[u8]) -> Result<()> {
+        let file_path = self.file_path(id)?;
+        let shard_dir = file_path
+            .parent()
+            .ok_or_else(|| Error::Logic(format!("No parent dir for {}", file_path.display())))?;
+        fs::create_dir_all(shard_dir)?;
+
+        let mut temp_file_name = file_path.as_os_str().to_owned();
+        temp_file_name.push(utils::TEMP_FILE_SUFFIX);
+        let temp_file_path = PathBuf::from(temp_file_name);
+
+        let res = File::create(&temp_file_path)
+            .and_then(|mut file| {
+                file.write_all(value)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp_file_path, &file_path))
+            // Persist the rename itself.
+            .and_then(|_| File::open(shard_dir)?.sync_all());
+
+        if let Err(error) = res {
+            let _ = fs::remove_file(&temp_file_path);
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    fn delete<I: ChunkId>(&mut self, id: &I) -> Result<()> {
+        let file_path = self.file_path(id)?;
+        if fs::metadata(&file_path).is_ok() {
+            fs::remove_file(file_path)?;
+        }
+        Ok(())
+    }
+
+    fn keys<I: ChunkId>(&self) -> Vec<I> {
+        let mut entries = Vec::new();
+        list_chunk_files(&self.dir, SHARD_LEVELS, &mut entries);
+        entries.iter().filter_map(to_chunk_id).collect()
+    }
+
+    /// Moves the chunk file to `QUARANTINE_DIR`, out of reach of the sharded layout.
+    fn quarantine<I: ChunkId>(&mut self, id: &I) -> Result<()> {
+        let file_path = self.file_path(id)?;
+        let file_name = match file_path.file_name() {
+            Some(file_name) if file_path.exists() => file_name,
+            _ => return Ok(()),
+        };
+        let quarantine_dir = self.dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&quarantine_dir)?;
+        fs::rename(&file_path, quarantine_dir.join(file_name))?;
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<u64> {
+        let mut entries = Vec::new();
+        list_chunk_files(&self.dir, SHARD_LEVELS, &mut entries);
+
+        let mut total = 0;
+        for entry in entries {
+            if is_temp_file(&entry) {
+                info!("Removing stray temp file {}", entry.path().display());
+                fs::remove_file(entry.path())?;
+            } else {
+                total += entry.metadata()?.len();
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// Keeps all chunks in memory. Nothing is persisted, so this is mostly useful for tests.
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Default)]
+pub(crate) struct MemoryBackend {
+    values: BTreeMap<Bytes, Vec<u8>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl MemoryBackend {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn dir(&self) -> Option<&Path> {
+        None
+    }
+
+    fn has<I: ChunkId>(&self, id: &I) -> bool {
+        utils::serialise(id)
+            .map(|key| self.values.contains_key(&key))
+            .unwrap_or(false)
+    }
+
+    fn size<I: ChunkId>(&self, id: &I) -> Option<u64> {
+        let key = utils::serialise(id).ok()?;
+        self.values.get(&key).map(|value| value.len() as u64)
+    }
+
+    fn get<I: ChunkId>(&self, id: &I) -> Result<Option<Vec<u8>>> {
+        Ok(self.values.get(&utils::serialise(id)?).cloned())
+    }
+
+    fn put<I: ChunkId>(&mut self, id: &I, value: &[u8]) -> Result<()> {
+        let _ = self.values.insert(utils::serialise(id)?, value.to_vec());
+        Ok(())
+    }
+
+    fn delete<I: ChunkId>(&mut self, id: &I) -> Result<()> {
+        let _ = self.values.remove(&utils::serialise(id)?);
+        Ok(())
+    }
+
+    fn keys<I: ChunkId>(&self) -> Vec<I> {
+        self.values
+            .keys()
+            .filter_map(|key| bincode::deserialize(key).ok())
+            .collect()
+    }
+
+    fn quarantine<I: ChunkId>(&mut self, id: &I) -> Result<()> {
+        self.delete(id)
+    }
+
+    fn recover(&mut self) -> Result<u64> {
+        Ok(self.values.values().map(|value| value.len() as u64).sum())
+    }
+}
+
+// Collects the file entries found `depth` levels of subdirectories below `dir`.
+fn list_chunk_files(dir: &Path, depth: usize, files: &mut Vec<DirEntry>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+        if depth == 0 && !is_dir {
+            files.push(entry);
+        } else if depth > 0 && is_dir {
+            list_chunk_files(&entry.path(), depth - 1, files);
+        }
+    }
+}
+
+fn is_temp_file(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_string_lossy()
+        .ends_with(utils::TEMP_FILE_SUFFIX)
+}
+
+fn to_chunk_id<T: ChunkId>(entry: &DirEntry) -> Option<T> {
+    let file_name = entry.file_name();
+    let file_name = file_name.into_string().ok()?;
+    let bytes = hex::decode(file_name).ok()?;
+    bincode::deserialize(&bytes).ok()
+}
@@ -9,6 +9,7 @@
 use crate::to_db_key::ToDbKey;
 use serde::{de::DeserializeOwned, Serialize};
 use sn_data_types::DataAddress;
+use xor_name::XorName;
 
 pub(crate) trait Chunk: Serialize + DeserializeOwned {
     type Id: ChunkId;
@@ -17,4 +18,6 @@ pub(crate) trait Chunk: Serialize + DeserializeOwned {
 
 pub(crate) trait ChunkId: ToDbKey + PartialEq + Eq + DeserializeOwned {
     fn to_data_address(&self) -> DataAddress;
+    /// The name the chunk is stored under, used to shard the on-disk layout.
+    fn name(&self) -> XorName;
 }
@@ -0,0 +1,65 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Encoding of serialised chunks for storage, optionally compressed.
+//!
+//! A compressed chunk is stored behind a header made of `MAGIC` and a format byte. Anything else
+//! is the plain serialised chunk, as stored before compression was supported, so both kinds of
+//! files can coexist in a store.
+
+use crate::Result;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::de::DeserializeOwned;
+use std::io::{Read, Write};
+
+/// Marks the start of a format header.
+const MAGIC: [u8; 4] = *b"SNCK";
+/// Format byte of a chunk compressed with deflate.
+const FORMAT_DEFLATE: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// Returns the bytes to store for the `serialised` chunk.
+///
+/// If `compress` is set and compressing pays off, these are the compressed bytes behind a header,
+/// otherwise the serialised chunk itself.
+pub(super) fn encode(serialised: &[u8], compress: bool) -> Result<Vec<u8>> {
+    if !compress {
+        return Ok(serialised.to_vec());
+    }
+
+    let mut header = MAGIC.to_vec();
+    header.push(FORMAT_DEFLATE);
+    let mut encoder = DeflateEncoder::new(header, Compression::default());
+    encoder.write_all(serialised)?;
+    let encoded = encoder.finish()?;
+
+    if encoded.len() < serialised.len() {
+        Ok(encoded)
+    } else {
+        Ok(serialised.to_vec())
+    }
+}
+
+/// Deserialises a chunk from its stored bytes, as returned by `encode`.
+pub(super) fn decode<T: DeserializeOwned>(stored: &[u8]) -> Result<T> {
+    if stored.len() >= HEADER_LEN
+        && stored[..MAGIC.len()] == MAGIC
+        && stored[MAGIC.len()] == FORMAT_DEFLATE
+    {
+        let mut serialised = vec![];
+        let decoded = DeflateDecoder::new(&stored[HEADER_LEN..])
+            .read_to_end(&mut serialised)
+            .ok()
+            .and_then(|_| bincode::deserialize(&serialised).ok());
+        // A plain serialised chunk could happen to start like a header.
+        if let Some(chunk) = decoded {
+            return Ok(chunk);
+        }
+    }
+    Ok(bincode::deserialize(stored)?)
+}
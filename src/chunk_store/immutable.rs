@@ -8,6 +8,7 @@
 
 use super::chunk::{Chunk, ChunkId};
 use sn_data_types::{Blob, BlobAddress, DataAddress};
+use xor_name::XorName;
 
 impl Chunk for Blob {
     type Id = BlobAddress;
@@ -23,4 +24,8 @@ impl ChunkId for BlobAddress {
     fn to_data_address(&self) -> DataAddress {
         DataAddress::Blob(*self)
     }
+
+    fn name(&self) -> XorName {
+        *BlobAddress::name(self)
+    }
 }
@@ -8,7 +8,9 @@
 
 //! A simple, persistent, disk-based key-value store.
 
+mod backend;
 mod chunk;
+mod compression;
 mod immutable;
 mod mutable;
 mod register;
@@ -17,14 +19,16 @@ mod sequence;
 mod tests;
 mod used_space;
 
+use crate::encryption::{self, EncryptionKey};
 use crate::error::{Error, Result};
 use crate::utils;
-use chunk::{Chunk, ChunkId};
+#[cfg(test)]
+pub(crate) use backend::MemoryBackend;
+pub(crate) use backend::{FileBackend, StorageBackend};
+pub(crate) use chunk::{Chunk, ChunkId};
 use log::{info, trace};
 use sn_data_types::{register::Register, Blob, Map, Sequence};
 use std::{
-    fs::{self, DirEntry, File, Metadata},
-    io::{Read, Write},
     marker::PhantomData,
     path::{Path, PathBuf},
 };
@@ -33,18 +37,22 @@ pub use used_space::UsedSpace;
 
 const CHUNK_STORE_DIR: &str = "chunks";
 
-/// The max name length for a chunk file.
-const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
-
 pub(crate) type BlobChunkStore = ChunkStore<Blob>;
 pub(crate) type MapChunkStore = ChunkStore<Map>;
 pub(crate) type SequenceChunkStore = ChunkStore<Sequence>;
 pub(crate) type RegisterChunkStore = ChunkStore<Register>;
 
-/// `ChunkStore` is a store of data held as serialised files on disk, implementing a maximum disk
-/// usage to restrict storage.
-pub(crate) struct ChunkStore<T: Chunk> {
-    dir: PathBuf,
+/// `ChunkStore` is a store of serialised data chunks, implementing a maximum usage to restrict
+/// storage.
+///
+/// The chunks are kept in a `StorageBackend`, which defaults to one file per chunk on disk (see
+/// `FileBackend`).
+pub(crate) struct ChunkStore<T: Chunk, B: StorageBackend = FileBackend> {
+    backend: B,
+    // Whether to compress chunks when storing them.
+    compress: bool,
+    // Key to encrypt chunks with when storing them, if any.
+    encryption: Option<EncryptionKey>,
     // Maximum space allowed for all `ChunkStore`s to consume.
     used_space: UsedSpace,
     id: StoreId,
@@ -59,38 +67,120 @@ where
     /// Creates a new `ChunkStore` at location `root/CHUNK_STORE_DIR/<chunk type>`.
     ///
     /// If the location specified already exists, the previous ChunkStore there is opened, otherwise
-    /// the required folder structure is created.
+    /// the required folder structure is created. A previous ChunkStore using the flat layout (all
+    /// chunk files directly in the store directory) is migrated to the sharded layout on opening.
     ///
     /// The maximum storage space is defined by `max_capacity`.  This specifies the max usable by
-    /// _all_ `ChunkStores`, not per `ChunkStore`.
-    pub async fn new<P: AsRef<Path>>(root: P, max_capacity: u64) -> Result<Self> {
+    /// _all_ `ChunkStores`, not per `ChunkStore`. If `compress` is set, chunks are compressed when
+    /// stored, and if an `encryption` key is given they are encrypted with it.
+    pub async fn new<P: AsRef<Path>>(
+        root: P,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
         let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
+        let backend = FileBackend::open::<T::Id>(dir)?;
+        Self::with_backend(backend, max_capacity, compress, encryption).await
+    }
 
-        if fs::read(&dir).is_err() {
-            Self::create_new_root(&dir)?
+    /// Opens the `ChunkStore` at `root` without changing anything on disk, e.g. to inspect it
+    /// while the node is stopped.
+    ///
+    /// Unlike `new`, no migration or recovery is run, and the used space record is left as it is.
+    /// Returns `Error::Logic` if there is no such store.
+    pub async fn open_read_only<P: AsRef<Path>>(
+        root: P,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
+        if !dir.is_dir() {
+            return Err(Error::Logic(format!("No chunk store at {}", dir.display())));
         }
-
-        let used_space = UsedSpace::new(max_capacity);
-        let id = used_space.add_local_store(&dir).await?;
+        let used_space = UsedSpace::new(u64::MAX);
+        let id = used_space.add_volatile_store().await;
         Ok(ChunkStore {
-            dir,
+            backend: FileBackend::existing(dir),
+            compress: false,
+            encryption,
             used_space,
             id,
             _phantom: PhantomData,
         })
     }
+
+    /// Returns the total size in bytes of the chunks stored.
+    pub fn stored_size(&self) -> u64 {
+        self.backend.stored_size()
+    }
+
+    /// Returns the used space recorded for this store, if any.
+    pub fn recorded_used_space(&self) -> Result<Option<u64>> {
+        match self.backend.dir() {
+            Some(dir) => used_space::read_record(dir),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the temp files left behind by writes interrupted by a crash.
+    pub fn temp_files(&self) -> Vec<PathBuf> {
+        self.backend.temp_files()
+    }
+
+    /// Migrates the `ChunkStore` at `root` from the flat layout to the sharded layout, without
+    /// opening it. Does nothing if there is no such store or it is already sharded.
+    pub fn migrate_flat_layout<P: AsRef<Path>>(root: P) -> Result<()> {
+        let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        FileBackend::migrate_flat_layout::<T::Id>(&dir)
+    }
+
+    /// Encrypts with `key` the chunks of the `ChunkStore` at `root` stored before encryption was
+    /// enabled, without opening it. Does nothing if there is no such store.
+    pub fn encrypt_plaintext<P: AsRef<Path>>(root: P, key: &EncryptionKey) -> Result<()> {
+        let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        FileBackend::encrypt_plaintext(&dir, key)
+    }
 }
 
-impl<T: Chunk> ChunkStore<T> {
-    fn create_new_root(root: &Path) -> Result<()> {
-        fs::create_dir_all(root)?;
+impl<T: Chunk, B: StorageBackend> ChunkStore<T, B> {
+    /// Creates a new `ChunkStore` keeping its chunks in the given `backend`.
+    ///
+    /// Any leftovers of writes interrupted by a crash are cleaned up first, and the used space
+    /// record is rebuilt from what the backend actually holds. If the backend persists to a
+    /// directory, the used space record is kept there too and the store can't grow past the free
+    /// space on its volume, otherwise it is only tracked in memory.
+    pub async fn with_backend(
+        mut backend: B,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let stored = backend.recover()?;
 
-        // Verify that chunk files can be created.
-        let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
-        let _ = File::create(&temp_file_path)?;
-        fs::remove_file(temp_file_path)?;
+        let used_space = UsedSpace::new(max_capacity);
+        let id = match backend.dir() {
+            Some(dir) => {
+                used_space.limit_to_free_space(dir).await;
+                used_space.add_local_store(dir).await?
+            }
+            None => used_space.add_volatile_store().await,
+        };
+        used_space.restore(id, stored).await?;
 
-        Ok(())
+        Ok(ChunkStore {
+            backend,
+            compress,
+            encryption,
+            used_space,
+            id,
+            _phantom: PhantomData,
+        })
     }
 
     /// Stores a new data chunk.
@@ -98,40 +188,53 @@ impl<T: Chunk> ChunkStore<T> {
     /// If there is not enough storage space available, returns `Error::NotEnoughSpace`.  In case of
     /// an IO error, it returns `Error::Io`.
     ///
-    /// If a chunk with the same id already exists, it will be overwritten.
+    /// If a chunk with the same id already exists, it will be overwritten. The previous chunk is
+    /// kept in place until the new one has been completely written.
+    ///
+    /// The space consumed is that of the chunk as stored, i.e. after any compression and
+    /// encryption.
     pub async fn put(&mut self, chunk: &T) -> Result<()> {
         info!("Writing chunk");
-        let serialised_chunk = utils::serialise(chunk)?;
-        let consumed_space = serialised_chunk.len() as u64;
+        let encoded = compression::encode(&utils::serialise(chunk)?, self.compress)?;
+        let stored_chunk = encryption::encrypt(self.encryption.as_ref(), encoded)?;
+        let consumed_space = stored_chunk.len() as u64;
 
         info!("consumed space: {:?}", consumed_space);
         info!("max : {:?}", self.used_space.max_capacity().await);
         info!("use space total : {:?}", self.used_space.total().await);
 
-        let file_path = self.file_path(chunk.id())?;
-        self.do_delete(&file_path).await?;
+        let replaced_space = self.backend.size(chunk.id());
 
         // pre-reserve space
-        self.used_space.increase(self.id, consumed_space).await?;
+        match replaced_space {
+            Some(replaced_space) => {
+                self.used_space
+                    .replace(self.id, replaced_space, consumed_space)
+                    .await?
+            }
+            None => self.used_space.increase(self.id, consumed_space).await?,
+        }
         trace!(
             "use space total after add: {:?}",
             self.used_space.total().await
         );
 
-        let res = File::create(&file_path).and_then(|mut file| {
-            file.write_all(&serialised_chunk)?;
-            file.sync_all()
-        });
-
-        match res {
+        match self.backend.put(chunk.id(), &stored_chunk) {
             Ok(_) => {
                 info!("Writing chunk succeeded!");
                 Ok(())
             }
             Err(e) => {
                 info!("Writing chunk failed!");
-                self.used_space.decrease(self.id, consumed_space).await?;
-                Err(e.into())
+                match replaced_space {
+                    Some(replaced_space) => {
+                        self.used_space
+                            .replace(self.id, consumed_space, replaced_space)
+                            .await?
+                    }
+                    None => self.used_space.decrease(self.id, consumed_space).await?,
+                }
+                Err(e)
             }
         }
     }
@@ -141,14 +244,20 @@ impl<T: Chunk> ChunkStore<T> {
     /// If the data doesn't exist, it does nothing and returns `Ok`.  In the case of an IO error, it
     /// returns `Error::Io`.
     pub async fn delete(&mut self, id: &T::Id) -> Result<()> {
-        self.do_delete(&self.file_path(id)?).await
+        self.do_delete(id).await
     }
 
-    /// Used space to max space ratio.
+    /// Used space to max space ratio, the max space being whichever is tighter of the max capacity
+    /// and the free space on the volume holding the store.
     pub async fn used_space_ratio(&self) -> f64 {
         let used = self.total_used_space().await;
-        let total = self.used_space.max_capacity().await;
-        let used_space_ratio = used as f64 / total as f64;
+        let total = self.used_space.capacity().await;
+        let used_space_ratio = if total == 0 {
+            // No space at all, e.g. the volume is full.
+            1.0
+        } else {
+            used as f64 / total as f64
+        };
         info!("Used space: {:?}", used);
         info!("Total space: {:?}", total);
         info!("Used space ratio: {:?}", used_space_ratio);
@@ -157,13 +266,13 @@ impl<T: Chunk> ChunkStore<T> {
 
     /// Returns a data chunk previously stored under `id`.
     ///
-    /// If the data file can't be accessed, it returns `Error::NoSuchChunk`.
+    /// If the data can't be accessed, it returns `Error::NoSuchChunk`.
     pub fn get(&self, id: &T::Id) -> Result<T> {
-        let mut file = File::open(self.file_path(id)?)
-            .map_err(|_| Error::NoSuchChunk(id.to_data_address()))?;
-        let mut contents = vec![];
-        let _ = file.read_to_end(&mut contents)?;
-        let chunk = bincode::deserialize::<T>(&contents)?;
+        let contents = self
+            .backend
+            .get(id)?
+            .ok_or_else(|| Error::NoSuchChunk(id.to_data_address()))?;
+        let chunk = self.decode(contents)?;
         // Check it's the requested chunk variant.
         if chunk.id() == id {
             Ok(chunk)
@@ -172,46 +281,61 @@ impl<T: Chunk> ChunkStore<T> {
         }
     }
 
+    /// Checks that the chunk stored under `id` still reads back as the chunk with that id.
+    ///
+    /// For immutable chunks the id is derived from the hash of the content, so this detects any
+    /// corruption of the stored bytes. If there is no chunk under `id`, returns
+    /// `Error::NoSuchChunk`.
+    pub fn is_intact(&self, id: &T::Id) -> Result<bool> {
+        let contents = self
+            .backend
+            .get(id)?
+            .ok_or_else(|| Error::NoSuchChunk(id.to_data_address()))?;
+        Ok(matches!(self.decode(contents), Ok(chunk) if chunk.id() == id))
+    }
+
+    // Deserialises a chunk from its stored bytes, as written by `put`.
+    fn decode(&self, stored: Vec<u8>) -> Result<T> {
+        let encoded = encryption::decrypt(self.encryption.as_ref(), stored)?;
+        compression::decode(&encoded)
+    }
+
+    /// Takes the chunk stored under `id` out of the store, e.g. after it failed `is_intact`.
+    ///
+    /// The chunk is no longer counted as used space, nor listed or returned by the store. Where the
+    /// backend persists to disk the file is kept aside in a quarantine directory for inspection.
+    pub async fn quarantine(&mut self, id: &T::Id) -> Result<()> {
+        if let Some(size) = self.backend.size(id) {
+            self.used_space.decrease(self.id, size).await?;
+            self.backend.quarantine(id)
+        } else {
+            Ok(())
+        }
+    }
+
     pub async fn total_used_space(&self) -> u64 {
         self.used_space.total().await
     }
 
     /// Tests if a data chunk has been previously stored under `id`.
     pub fn has(&self, id: &T::Id) -> bool {
-        if let Ok(path) = self.file_path(id) {
-            fs::metadata(path)
-                .as_ref()
-                .map(Metadata::is_file)
-                .unwrap_or(false)
-        } else {
-            false
-        }
+        self.backend.has(id)
     }
 
     /// Lists all keys of currently stored data.
     #[cfg_attr(not(test), allow(unused))]
     pub fn keys(&self) -> Vec<T::Id> {
-        fs::read_dir(&self.dir)
-            .map(|entries| {
-                entries
-                    .filter_map(|entry| to_chunk_id(&entry.ok()?))
-                    .collect()
-            })
-            .unwrap_or_else(|_| Vec::new())
-    }
-
-    async fn do_delete(&mut self, file_path: &Path) -> Result<()> {
-        if let Ok(metadata) = fs::metadata(file_path) {
-            self.used_space.decrease(self.id, metadata.len()).await?;
-            fs::remove_file(file_path).map_err(From::from)
+        self.backend.keys()
+    }
+
+    async fn do_delete(&mut self, id: &T::Id) -> Result<()> {
+        if let Some(size) = self.backend.size(id) {
+            self.used_space.decrease(self.id, size).await?;
+            self.backend.delete(id)
         } else {
             Ok(())
         }
     }
-
-    fn file_path(&self, id: &T::Id) -> Result<PathBuf> {
-        Ok(self.dir.join(&hex::encode(utils::serialise(id)?)))
-    }
 }
 
 pub(crate) trait Subdir {
@@ -241,10 +365,3 @@ impl Subdir for RegisterChunkStore {
         Path::new("register")
     }
 }
-
-fn to_chunk_id<T: ChunkId>(entry: &DirEntry) -> Option<T> {
-    let file_name = entry.file_name();
-    let file_name = file_name.into_string().ok()?;
-    let bytes = hex::decode(file_name).ok()?;
-    bincode::deserialize(&bytes).ok()
-}
@@ -8,6 +8,7 @@
 
 use super::chunk::{Chunk, ChunkId};
 use sn_data_types::{DataAddress, Map, MapAddress};
+use xor_name::XorName;
 
 impl Chunk for Map {
     type Id = MapAddress;
@@ -23,4 +24,8 @@ impl ChunkId for MapAddress {
     fn to_data_address(&self) -> DataAddress {
         DataAddress::Map(*self)
     }
+
+    fn name(&self) -> XorName {
+        *MapAddress::name(self)
+    }
 }
@@ -11,6 +11,7 @@ use sn_data_types::{
     register::{Address, Register},
     DataAddress,
 };
+use xor_name::XorName;
 
 impl Chunk for Register {
     type Id = Address;
@@ -23,4 +24,8 @@ impl ChunkId for Address {
     fn to_data_address(&self) -> DataAddress {
         DataAddress::Register(*self)
     }
+
+    fn name(&self) -> XorName {
+        *Address::name(self)
+    }
 }
@@ -8,6 +8,7 @@
 
 use super::chunk::{Chunk, ChunkId};
 use sn_data_types::{DataAddress, Sequence, SequenceAddress};
+use xor_name::XorName;
 
 impl Chunk for Sequence {
     type Id = SequenceAddress;
@@ -20,4 +21,8 @@ impl ChunkId for SequenceAddress {
     fn to_data_address(&self) -> DataAddress {
         DataAddress::Sequence(*self)
     }
+
+    fn name(&self) -> XorName {
+        *SequenceAddress::name(self)
+    }
 }
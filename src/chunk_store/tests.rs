@@ -8,14 +8,14 @@
 
 use super::{
     chunk::{Chunk, ChunkId},
-    ChunkStore, Result as ChunkStoreResult, Subdir,
+    ChunkStore, MemoryBackend, Result as ChunkStoreResult, Subdir,
 };
-use crate::{to_db_key::ToDbKey, Error, Result};
+use crate::{encryption::EncryptionKey, to_db_key::ToDbKey, utils, Error, Result};
 use rand::{distributions::Standard, rngs::ThreadRng, Rng};
 use serde::{Deserialize, Serialize};
 use sn_data_types::{BlobAddress, DataAddress};
 use sn_routing::XorName;
-use std::{path::Path, u64};
+use std::{fs, path::Path, u64};
 use tempdir::TempDir;
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -38,9 +38,11 @@ struct Id(u64);
 impl ToDbKey for Id {}
 impl ChunkId for Id {
     fn to_data_address(&self) -> DataAddress {
-        DataAddress::Blob(BlobAddress::Public(XorName::from_content(&[&self
-            .0
-            .to_be_bytes()])))
+        DataAddress::Blob(BlobAddress::Public(self.name()))
+    }
+
+    fn name(&self) -> XorName {
+        XorName::from_content(&[&self.0.to_be_bytes()])
     }
 }
 
@@ -94,7 +96,7 @@ async fn successful_put() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::<Data>::new(root.path(), u64::MAX).await?;
+    let mut chunk_store = ChunkStore::<Data>::new(root.path(), u64::MAX, false, None).await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate().rev() {
         let the_data = &Data {
@@ -129,7 +131,7 @@ async fn failed_put_when_not_enough_space() -> Result<()> {
     let mut rng = new_rng();
     let root = temp_dir()?;
     let capacity = 32;
-    let mut chunk_store = ChunkStore::new(root.path(), capacity).await?;
+    let mut chunk_store = ChunkStore::new(root.path(), capacity, false, None).await?;
 
     let data = Data {
         id: Id(rng.gen()),
@@ -153,7 +155,7 @@ async fn delete() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate() {
         let the_data = &Data {
@@ -177,7 +179,7 @@ async fn put_and_get_value_should_be_same() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         chunk_store
@@ -202,7 +204,7 @@ async fn overwrite_value() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
 
     for (data, size) in chunks.data_and_sizes {
         chunk_store
@@ -222,7 +224,7 @@ async fn overwrite_value() -> Result<()> {
 #[tokio::test]
 async fn get_fails_when_key_does_not_exist() -> Result<()> {
     let root = temp_dir()?;
-    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX).await?;
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
 
     let id = Id(new_rng().gen());
     match chunk_store.get(&id) {
@@ -239,7 +241,7 @@ async fn keys() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         let id = Id(index as u64);
@@ -269,3 +271,250 @@ async fn keys() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn migrates_flat_layout() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    let root = temp_dir()?;
+    let store_dir = root.path().join(super::CHUNK_STORE_DIR).join("test");
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+
+    let mut flat_files = vec![];
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let id = Id(index as u64);
+        chunk_store
+            .put(&Data {
+                id,
+                value: data.clone(),
+            })
+            .await?;
+        // Move the chunk file out of its shard, back to where the flat layout kept it.
+        let sharded_path = chunk_store.backend.file_path(&id)?;
+        let flat_path =
+            store_dir.join(sharded_path.file_name().ok_or_else(|| {
+                Error::Logic(format!("No file name in {}", sharded_path.display()))
+            })?);
+        fs::rename(&sharded_path, &flat_path)?;
+        flat_files.push(flat_path);
+    }
+    let used_space = chunk_store.used_space.local(chunk_store.id).await;
+    drop(chunk_store);
+
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+
+    assert!(flat_files.iter().all(|path| !path.exists()));
+    assert_eq!(
+        chunk_store.used_space.local(chunk_store.id).await,
+        used_space
+    );
+
+    let mut keys = chunk_store.keys();
+    keys.sort();
+    assert_eq!(
+        (0..chunks.data_and_sizes.len())
+            .map(|i| Id(i as u64))
+            .collect::<Vec<_>>(),
+        keys
+    );
+
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let id = Id(index as u64);
+        assert!(chunk_store.has(&id));
+        assert_eq!(*data, chunk_store.get(&id)?.value);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recovers_from_interrupted_writes() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    let root = temp_dir()?;
+    let store_dir = root.path().join(super::CHUNK_STORE_DIR).join("test");
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        chunk_store
+            .put(&Data {
+                id: Id(index as u64),
+                value: data.clone(),
+            })
+            .await?
+    }
+    drop(chunk_store);
+
+    // Leave behind what a crash mid-write would: a temp file, and a stale used space record.
+    let shard_dir = store_dir.join("00").join("00");
+    fs::create_dir_all(&shard_dir)?;
+    let temp_file = shard_dir.join(format!("{}.tmp", hex::encode(utils::serialise(&Id(0))?)));
+    fs::write(&temp_file, [0u8; 16])?;
+    fs::write(
+        store_dir.join("used_space"),
+        utils::serialise(&(chunks.total_size + 1))?,
+    )?;
+
+    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+
+    assert!(!temp_file.exists());
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+    assert_eq!(
+        chunk_store.used_space.local(chunk_store.id).await,
+        chunks.total_size
+    );
+    assert_eq!(chunk_store.keys().len(), chunks.data_and_sizes.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn in_memory_backend() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    let mut chunk_store =
+        ChunkStore::<Data, _>::with_backend(MemoryBackend::new(), chunks.total_size, false, None)
+            .await?;
+
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        chunk_store
+            .put(&Data {
+                id: Id(index as u64),
+                value: data.clone(),
+            })
+            .await?
+    }
+    assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
+
+    let mut keys = chunk_store.keys();
+    keys.sort();
+    assert_eq!(
+        (0..chunks.data_and_sizes.len())
+            .map(|i| Id(i as u64))
+            .collect::<Vec<_>>(),
+        keys
+    );
+
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let id = Id(index as u64);
+        assert_eq!(*data, chunk_store.get(&id)?.value);
+        chunk_store.delete(&id).await?;
+        assert!(!chunk_store.has(&id));
+    }
+    assert_eq!(chunk_store.total_used_space().await, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn quarantines_corrupt_chunk() -> Result<()> {
+    let root = temp_dir()?;
+    let store_dir = root.path().join(super::CHUNK_STORE_DIR).join("test");
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+
+    let data = Data {
+        id: Id(0),
+        value: vec![1; 32],
+    };
+    chunk_store.put(&data).await?;
+    assert!(chunk_store.is_intact(&data.id)?);
+
+    // Flip some bits of the stored id, as a bad disk could.
+    let path = chunk_store.backend.file_path(&data.id)?;
+    let mut contents = fs::read(&path)?;
+    contents[0] ^= 0xff;
+    fs::write(&path, &contents)?;
+    assert!(!chunk_store.is_intact(&data.id)?);
+
+    chunk_store.quarantine(&data.id).await?;
+    assert!(!chunk_store.has(&data.id));
+    assert!(chunk_store.keys().is_empty());
+    assert_eq!(chunk_store.total_used_space().await, 0);
+    assert!(store_dir
+        .join("quarantine")
+        .join(
+            path.file_name()
+                .ok_or_else(|| Error::Logic("No file name".to_string()))?
+        )
+        .exists());
+    match chunk_store.is_intact(&data.id) {
+        Err(Error::NoSuchChunk(_)) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compressed_and_plain_chunks_coexist() -> Result<()> {
+    let root = temp_dir()?;
+    let plain = Data {
+        id: Id(0),
+        value: vec![7; 1024],
+    };
+    let compressed = Data {
+        id: Id(1),
+        value: vec![7; 1024],
+    };
+
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+    chunk_store.put(&plain).await?;
+    drop(chunk_store);
+
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, true, None).await?;
+    chunk_store.put(&compressed).await?;
+
+    let plain_size = fs::metadata(chunk_store.backend.file_path(&plain.id)?)?.len();
+    let compressed_size = fs::metadata(chunk_store.backend.file_path(&compressed.id)?)?.len();
+    assert_eq!(plain_size, bincode::serialized_size(&plain)?);
+    assert!(compressed_size < plain_size);
+    assert_eq!(
+        chunk_store.total_used_space().await,
+        plain_size + compressed_size
+    );
+
+    assert_eq!(chunk_store.get(&plain.id)?, plain);
+    assert_eq!(chunk_store.get(&compressed.id)?, compressed);
+    assert!(chunk_store.is_intact(&plain.id)?);
+    assert!(chunk_store.is_intact(&compressed.id)?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn encrypted_chunks_need_the_key() -> Result<()> {
+    let root = temp_dir()?;
+    let key = EncryptionKey::random();
+    let plain = Data {
+        id: Id(0),
+        value: vec![7; 1024],
+    };
+    let encrypted = Data {
+        id: Id(1),
+        value: vec![7; 1024],
+    };
+
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, false, None).await?;
+    chunk_store.put(&plain).await?;
+    drop(chunk_store);
+
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, true, Some(key.clone())).await?;
+    chunk_store.put(&encrypted).await?;
+
+    let contents = fs::read(chunk_store.backend.file_path(&encrypted.id)?)?;
+    assert!(!contents.windows(8).any(|window| window == [7; 8]));
+    assert_eq!(chunk_store.get(&plain.id)?, plain);
+    assert_eq!(chunk_store.get(&encrypted.id)?, encrypted);
+    assert!(chunk_store.is_intact(&encrypted.id)?);
+    drop(chunk_store);
+
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, false, Some(EncryptionKey::random())).await?;
+    assert!(chunk_store.get(&encrypted.id).is_err());
+    assert!(!chunk_store.is_intact(&encrypted.id)?);
+
+    Ok(())
+}
@@ -7,13 +7,31 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{Error, Result};
-use std::{path::Path, sync::Arc};
+use log::warn;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{io::AsyncSeekExt, sync::Mutex};
 
 const USED_SPACE_FILENAME: &str = "used_space";
 
+/// Free space left untouched on the volume holding the stores, so that the node and anything else
+/// on the machine can still write to it when the stores are full.
+pub const RESERVED_DISK_SPACE: u64 = 100 * 1024 * 1024;
+
+/// Time the free space found on the volume holding the stores is relied on before looking it up
+/// again, as the lookup is a syscall we don't want on every write.
+const FREE_SPACE_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
 /// This holds a record (in-memory and on-disk) of the space used by a single `ChunkStore`, and also
 /// an in-memory record of the total space used by all `ChunkStore`s.
+///
+/// The total is limited by `max_capacity`, and once `limit_to_free_space` has been called, also by
+/// the free space actually left on the volume holding the stores, minus `RESERVED_DISK_SPACE`.
+/// The free space is looked up at most every `FREE_SPACE_REFRESH_INTERVAL`, without holding the
+/// lock on the record.
 #[derive(Debug)]
 pub struct UsedSpace {
     inner: Arc<Mutex<inner::UsedSpace>>,
@@ -47,6 +65,13 @@ impl UsedSpace {
         self.inner.lock().await.max_capacity()
     }
 
+    /// Returns the value total() can actually grow to: the maximum capacity, or less if the volume
+    /// holding the stores hasn't got that much free space left
+    pub async fn capacity(&self) -> u64 {
+        self.refresh_free_space().await;
+        self.inner.lock().await.capacity()
+    }
+
     /// Returns the total used space as a snapshot
     /// Note, due to the async nature of this, the value
     /// may be stale by the time it is read if there are multiple
@@ -71,8 +96,32 @@ impl UsedSpace {
         self.inner.lock().await.add_local_store(dir).await
     }
 
+    /// Also limit the total to the free space left on the volume
+    /// holding `dir`, beyond `RESERVED_DISK_SPACE`
+    pub async fn limit_to_free_space<T: AsRef<Path>>(&self, dir: T) {
+        self.inner.lock().await.limit_to_free_space(dir);
+        self.refresh_free_space().await
+    }
+
+    // Looks up the free space on the volume again if the last lookup is out of date.
+    async fn refresh_free_space(&self) {
+        let dir = match self.inner.lock().await.volume_to_refresh() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let available = available_disk_space(&dir);
+        self.inner.lock().await.set_free_space(available);
+    }
+
+    /// Add an object to track used space of a single `ChunkStore`
+    /// which is not persisted anywhere, e.g. one held in memory
+    pub async fn add_volatile_store(&self) -> StoreId {
+        self.inner.lock().await.add_volatile_store()
+    }
+
     /// Increase the used amount of a single chunk store and the global used value
     pub async fn increase(&self, id: StoreId, consumed: u64) -> Result<()> {
+        self.refresh_free_space().await;
         self.inner.lock().await.increase(id, consumed).await
     }
 
@@ -80,12 +129,54 @@ impl UsedSpace {
     pub async fn decrease(&self, id: StoreId, released: u64) -> Result<()> {
         self.inner.lock().await.decrease(id, released).await
     }
+
+    /// Release `released` and consume `consumed` in a single chunk store and
+    /// the global used value at once, e.g. when overwriting a chunk
+    pub async fn replace(&self, id: StoreId, released: u64, consumed: u64) -> Result<()> {
+        self.refresh_free_space().await;
+        self.inner
+            .lock()
+            .await
+            .replace(id, released, consumed)
+            .await
+    }
+
+    /// Overwrite the used amount of a single chunk store with the space it
+    /// actually takes up, adjusting the global used value accordingly
+    pub async fn restore(&self, id: StoreId, used: u64) -> Result<()> {
+        self.inner.lock().await.restore(id, used).await
+    }
+}
+
+/// Reads the used space record kept in `dir`, if there is one.
+pub(crate) fn read_record(dir: &Path) -> Result<Option<u64>> {
+    let path = dir.join(USED_SPACE_FILENAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = std::fs::read(path)?;
+    if contents.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize(&contents)?))
+}
+
+/// Returns the free space on the volume holding `dir` available to the stores, i.e. minus
+/// `RESERVED_DISK_SPACE`. If it can't be determined, the volume is assumed to have no free space.
+fn available_disk_space(dir: &Path) -> u64 {
+    match fs2::available_space(dir) {
+        Ok(available) => available.saturating_sub(RESERVED_DISK_SPACE),
+        Err(e) => {
+            warn!("Could not get the free space at {}: {}", dir.display(), e);
+            0
+        }
+    }
 }
 
 mod inner {
 
     use super::*;
-    use std::{collections::HashMap, io::SeekFrom};
+    use std::{collections::HashMap, io::SeekFrom, path::PathBuf};
     use tokio::{
         fs::{File, OpenOptions},
         io::{AsyncReadExt, AsyncWriteExt},
@@ -103,6 +194,10 @@ mod inner {
         local_stores: HashMap<StoreId, LocalUsedSpace>,
         /// next local `ChunkStore` id to use
         next_id: StoreId,
+        /// a directory on the volume whose free space also limits `total_value`, if any
+        volume_dir: Option<PathBuf>,
+        /// the value `total_value` can grow to on that volume, and when it was looked up
+        volume_limit: Option<(u64, Instant)>,
     }
 
     /// An entry used to track the used space of a single `ChunkStore`
@@ -110,11 +205,11 @@ mod inner {
     struct LocalUsedSpace {
         // Space consumed by this one `ChunkStore`.
         pub local_value: u64,
-        // File used to maintain on-disk record of `local_value`.
+        // File used to maintain on-disk record of `local_value`, if the store is persisted.
         // TODO: maybe a good idea to maintain a journal that is only flushed occasionally
         // to ensure stale entries aren't recorded, and to avoid holding the lock for the
         // whole inner::UsedSpace struct during the entirety of the file write.
-        pub local_record: File,
+        pub local_record: Option<File>,
     }
 
     impl UsedSpace {
@@ -124,6 +219,8 @@ mod inner {
                 total_value: 0u64,
                 local_stores: HashMap::new(),
                 next_id: 0u64,
+                volume_dir: None,
+                volume_limit: None,
             }
         }
 
@@ -132,9 +229,12 @@ mod inner {
         /// the capacity and next_id unchanged
         pub async fn reset(&mut self) -> Result<()> {
             self.total_value = 0;
+            self.volume_limit = None;
             for (_id, local_used_space) in self.local_stores.iter_mut() {
                 local_used_space.local_value = 0;
-                Self::write_local_to_file(&mut local_used_space.local_record, 0).await?;
+                if let Some(record) = local_used_space.local_record.as_mut() {
+                    Self::write_local_to_file(record, 0).await?;
+                }
             }
             Ok(())
         }
@@ -145,6 +245,15 @@ mod inner {
             self.max_capacity
         }
 
+        /// Returns the value `total_value` can grow to, limited by the free space on the volume
+        /// as last looked up
+        pub fn capacity(&self) -> u64 {
+            match self.volume_limit {
+                Some((limit, _)) => self.max_capacity.min(limit),
+                None => self.max_capacity,
+            }
+        }
+
         /// Returns the total used space
         pub fn total(&self) -> u64 {
             self.total_value
@@ -182,12 +291,48 @@ mod inner {
 
             let local_store = LocalUsedSpace {
                 local_value,
-                local_record,
+                local_record: Some(local_record),
             };
+
+            self.total_value = self.total_value.saturating_add(local_value);
+            Ok(self.insert_local_store(local_store))
+        }
+
+        /// Limits `total_value` to the free space on the volume holding `dir`
+        pub fn limit_to_free_space<T: AsRef<Path>>(&mut self, dir: T) {
+            self.volume_dir = Some(dir.as_ref().to_path_buf());
+            self.volume_limit = None;
+        }
+
+        /// Returns the directory whose volume's free space is to be looked up again, if any
+        pub fn volume_to_refresh(&self) -> Option<PathBuf> {
+            match self.volume_limit {
+                Some((_, looked_up)) if looked_up.elapsed() < FREE_SPACE_REFRESH_INTERVAL => None,
+                _ => self.volume_dir.clone(),
+            }
+        }
+
+        /// Records the free space just looked up on the volume. The space used so far is added to
+        /// it, as our own writes take up the free space as much as they add to `total_value`.
+        pub fn set_free_space(&mut self, available: u64) {
+            let limit = self.total_value.saturating_add(available);
+            self.volume_limit = Some((limit, Instant::now()));
+        }
+
+        /// Adds a new record for tracking the actions
+        /// of a local chunk store which has no on-disk record
+        pub fn add_volatile_store(&mut self) -> StoreId {
+            self.insert_local_store(LocalUsedSpace {
+                local_value: 0,
+                local_record: None,
+            })
+        }
+
+        fn insert_local_store(&mut self, local_store: LocalUsedSpace) -> StoreId {
             let id = self.next_id;
             self.next_id += 1;
             let _ = self.local_stores.insert(id, local_store);
-            Ok(id)
+            id
         }
 
         /// Increase used space in a local store and globally at the same time
@@ -196,7 +341,7 @@ mod inner {
                 .total_value
                 .checked_add(consumed)
                 .ok_or(Error::NotEnoughSpace)?;
-            if new_total > self.max_capacity {
+            if new_total > self.capacity() {
                 return Err(Error::NotEnoughSpace);
             }
             let new_local = self
@@ -207,12 +352,13 @@ mod inner {
                 .checked_add(consumed)
                 .ok_or(Error::NotEnoughSpace)?;
 
+            if let Some(record) = self
+                .local_stores
+                .get_mut(&id)
+                .ok_or(Error::NoStoreId)?
+                .local_record
+                .as_mut()
             {
-                let record = &mut self
-                    .local_stores
-                    .get_mut(&id)
-                    .ok_or(Error::NoStoreId)?
-                    .local_record;
                 Self::write_local_to_file(record, new_local).await?;
             }
             self.total_value = new_total;
@@ -234,12 +380,13 @@ mod inner {
                 .saturating_sub(released);
 
             let new_total = self.total_value.saturating_sub(released);
+            if let Some(record) = self
+                .local_stores
+                .get_mut(&id)
+                .ok_or(Error::NoStoreId)?
+                .local_record
+                .as_mut()
             {
-                let record = &mut self
-                    .local_stores
-                    .get_mut(&id)
-                    .ok_or(Error::NoStoreId)?
-                    .local_record;
                 Self::write_local_to_file(record, new_local).await?;
             }
             self.total_value = new_total;
@@ -250,6 +397,53 @@ mod inner {
             Ok(())
         }
 
+        /// Replace used space in a local store and globally at the same time
+        pub async fn replace(&mut self, id: StoreId, released: u64, consumed: u64) -> Result<()> {
+            let new_total = self
+                .total_value
+                .saturating_sub(released)
+                .checked_add(consumed)
+                .ok_or(Error::NotEnoughSpace)?;
+            if new_total > self.capacity() {
+                return Err(Error::NotEnoughSpace);
+            }
+            let new_local = self
+                .local_stores
+                .get(&id)
+                .ok_or(Error::NoStoreId)?
+                .local_value
+                .saturating_sub(released)
+                .checked_add(consumed)
+                .ok_or(Error::NotEnoughSpace)?;
+
+            self.set_local(id, new_local).await?;
+            self.total_value = new_total;
+            Ok(())
+        }
+
+        /// Set the used space of a local store to `used`, whatever the
+        /// max capacity, adjusting the global value by the difference
+        pub async fn restore(&mut self, id: StoreId, used: u64) -> Result<()> {
+            let old_local = self.local(id);
+            self.set_local(id, used).await?;
+            self.total_value = self
+                .total_value
+                .saturating_sub(old_local)
+                .saturating_add(used);
+            // The free space last looked up no longer adds up with the space used.
+            self.volume_limit = None;
+            Ok(())
+        }
+
+        async fn set_local(&mut self, id: StoreId, value: u64) -> Result<()> {
+            let local_store = self.local_stores.get_mut(&id).ok_or(Error::NoStoreId)?;
+            if let Some(record) = local_store.local_record.as_mut() {
+                Self::write_local_to_file(record, value).await?;
+            }
+            local_store.local_value = value;
+            Ok(())
+        }
+
         /// helper to write the contents of local to file
         /// NOTE: For now, you should hold the lock on the inner while doing this
         /// It's slow, but maintains behaviour from the previous implementation
@@ -269,7 +463,7 @@ mod inner {
 
 #[cfg(test)]
 mod tests {
-    use super::{Error, Result, UsedSpace};
+    use super::{Error, Result, UsedSpace, RESERVED_DISK_SPACE};
     use tempdir::TempDir;
 
     const TEST_STORE_MAX_SIZE: u64 = u64::MAX;
@@ -332,4 +526,35 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn limited_by_free_space() -> Result<()> {
+        let root_dir = create_temp_root()?;
+        let store_dir = create_temp_store(&root_dir)?;
+        let used_space = UsedSpace::new(TEST_STORE_MAX_SIZE);
+        let id = used_space.add_local_store(&store_dir).await?;
+        used_space.increase(id, 1000).await?;
+        assert_eq!(used_space.capacity().await, TEST_STORE_MAX_SIZE);
+
+        used_space.limit_to_free_space(&store_dir).await;
+        let capacity = used_space.capacity().await;
+        let available = fs2::available_space(store_dir.path())?;
+        // Other writes to the volume may free or take up space in between.
+        let expected = 1000 + available.saturating_sub(RESERVED_DISK_SPACE);
+        assert!(capacity < TEST_STORE_MAX_SIZE);
+        assert!(capacity.max(expected) - capacity.min(expected) < 64 * 1024 * 1024);
+
+        // The max capacity still applies when tighter.
+        let small = UsedSpace::new(500);
+        small.limit_to_free_space(&store_dir).await;
+        assert_eq!(small.capacity().await, 500);
+
+        // Growing past the free space fails, whatever the max capacity.
+        assert!(matches!(
+            used_space.increase(id, available).await,
+            Err(Error::NotEnoughSpace)
+        ));
+        assert_eq!(used_space.total().await, 1000);
+        Ok(())
+    }
 }
@@ -8,16 +8,18 @@
 
 use crate::node_ops::MsgType;
 use crate::{
-    chunk_store::BlobChunkStore,
+    chunk_store::{BlobChunkStore, ChunkStore, FileBackend, StorageBackend},
+    encryption::EncryptionKey,
+    error::convert_to_error_message,
     node_ops::{NodeDuty, OutgoingMsg},
     Error, Result,
 };
-use log::{error, info};
+use log::{error, info, warn};
 use sn_data_types::{Blob, BlobAddress, DataAddress, PublicKey};
 use sn_messaging::{
-    client::Error as ErrorMessage,
-    node::{NodeDataQueryResponse, NodeMsg, NodeQueryResponse},
-    Aggregation, DstLocation, MessageId,
+    client::{CmdError, Error as ErrorMessage},
+    node::{NodeDataQueryResponse, NodeEvent, NodeMsg, NodeQueryResponse, NodeSystemQueryResponse},
+    Aggregation, DstLocation, MessageId, SrcLocation,
 };
 use std::{
     fmt::{self, Display, Formatter},
@@ -25,25 +27,55 @@ use std::{
 };
 
 /// Storage of data chunks.
-pub(crate) struct ChunkStorage {
-    chunks: BlobChunkStore,
+pub(crate) struct ChunkStorage<B: StorageBackend = FileBackend> {
+    chunks: ChunkStore<Blob, B>,
 }
 
 impl ChunkStorage {
     #[allow(dead_code)]
-    pub(crate) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = BlobChunkStore::new(path, max_capacity).await?;
+    pub(crate) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let chunks = BlobChunkStore::new(path, max_capacity, compress, encryption).await?;
         Ok(Self { chunks })
     }
+}
 
+impl<B: StorageBackend> ChunkStorage<B> {
     pub fn keys(&self) -> Vec<BlobAddress> {
         self.chunks.keys()
     }
 
-    pub(crate) async fn store(&mut self, data: &Blob) -> Result<NodeDuty> {
-        self.try_store(data).await?;
+    /// Stores a chunk sent by the Elders, acknowledging it to them for them to track our
+    /// liveness, and to store it elsewhere if we failed to.
+    pub(crate) async fn store(&mut self, data: &Blob, msg_id: MessageId) -> NodeDuty {
+        let result = match self.try_store(data).await {
+            // Already holding it is as good as having stored it.
+            Ok(()) | Err(Error::DataExists) => Ok(()),
+            Err(error) => {
+                warn!(
+                    "{}: Failed to store chunk {:?}: {}",
+                    self,
+                    data.address(),
+                    error
+                );
+                Err(CmdError::Data(convert_to_error_message(error)))
+            }
+        };
 
-        Ok(NodeDuty::NoOp)
+        NodeDuty::Send(OutgoingMsg {
+            msg: MsgType::Node(NodeMsg::NodeEvent {
+                event: NodeEvent::ChunkWriteHandled(result),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+            }),
+            section_source: false, // sent as single node
+            dst: DstLocation::Section(*data.name()),
+            aggregation: Aggregation::None,
+        })
     }
 
     async fn try_store(&mut self, data: &Blob) -> Result<()> {
@@ -83,6 +115,49 @@ impl ChunkStorage {
         })
     }
 
+    /// Returns our copy of a chunk to the Elders restoring it at another holder.
+    pub(crate) fn get_for_republish(
+        &self,
+        address: &BlobAddress,
+        msg_id: MessageId,
+        origin: SrcLocation,
+    ) -> NodeDuty {
+        match self.get_chunk(address) {
+            Ok(blob) => NodeDuty::Send(OutgoingMsg {
+                msg: MsgType::Node(NodeMsg::NodeQueryResponse {
+                    response: NodeQueryResponse::System(NodeSystemQueryResponse::GetChunk(blob)),
+                    id: MessageId::in_response_to(&msg_id),
+                    correlation_id: msg_id,
+                }),
+                section_source: false, // sent as single node
+                dst: origin.to_dst(),
+                aggregation: Aggregation::None,
+            }),
+            Err(error) => {
+                warn!(
+                    "{}: Cannot return chunk {:?} for republish: {:?}",
+                    self, address, error
+                );
+                NodeDuty::NoOp
+            }
+        }
+    }
+
+    /// Re-validates the content hash of the chunk at `address`, quarantining it if corrupt.
+    ///
+    /// Returns whether it was found corrupt, for a good copy to be restored from its other holders.
+    pub(crate) async fn scrub(&mut self, address: &BlobAddress) -> Result<bool> {
+        match self.chunks.is_intact(address) {
+            Ok(true) | Err(Error::NoSuchChunk(_)) => return Ok(false),
+            Ok(false) => (),
+            Err(error) => return Err(error),
+        }
+
+        warn!("{}: Quarantining corrupt chunk: {:?}", self, address);
+        self.chunks.quarantine(address).await?;
+        Ok(true)
+    }
+
     /// Stores a chunk that Elders sent to it for replication.
     pub async fn store_for_replication(&mut self, blob: Blob) -> Result<()> {
         if self.chunks.has(blob.address()) {
@@ -141,7 +216,7 @@ impl ChunkStorage {
     }
 }
 
-impl Display for ChunkStorage {
+impl<B: StorageBackend> Display for ChunkStorage<B> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "ChunkStorage")
     }
@@ -150,7 +225,7 @@ impl Display for ChunkStorage {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::Result;
+    use crate::{chunk_store::MemoryBackend, error::Result};
     use bls::SecretKey;
     use sn_data_types::{PrivateBlob, PublicBlob, PublicKey};
     use std::path::PathBuf;
@@ -167,7 +242,7 @@ mod tests {
     #[tokio::test]
     pub async fn try_store_stores_public_blob() -> Result<()> {
         let path = PathBuf::from(temp_dir()?.path());
-        let mut storage = ChunkStorage::new(&path, u64::MAX).await?;
+        let mut storage = ChunkStorage::new(&path, u64::MAX, false, None).await?;
         let value = "immutable data value".to_owned().into_bytes();
         let blob = Blob::Public(PublicBlob::new(value));
         assert!(storage.try_store(&blob).await.is_ok());
@@ -179,7 +254,7 @@ mod tests {
     #[tokio::test]
     pub async fn try_store_stores_private_blob() -> Result<()> {
         let path = PathBuf::from(temp_dir()?.path());
-        let mut storage = ChunkStorage::new(&path, u64::MAX).await?;
+        let mut storage = ChunkStorage::new(&path, u64::MAX, false, None).await?;
         let value = "immutable data value".to_owned().into_bytes();
         let key = get_random_pk();
         let blob = Blob::Private(PrivateBlob::new(value, key));
@@ -188,4 +263,43 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    pub async fn delete_removes_private_blob_of_owner() -> Result<()> {
+        let mut storage = ChunkStorage {
+            chunks: ChunkStore::with_backend(MemoryBackend::new(), u64::MAX, false, None).await?,
+        };
+        let value = "immutable data value".to_owned().into_bytes();
+        let owner = get_random_pk();
+        let blob = Blob::Private(PrivateBlob::new(value, owner));
+        storage.try_store(&blob).await?;
+
+        assert!(storage
+            .delete(*blob.address(), MessageId::new(), get_random_pk())
+            .await
+            .is_err());
+        assert!(storage.chunks.has(blob.address()));
+
+        let _ = storage
+            .delete(*blob.address(), MessageId::new(), owner)
+            .await?;
+        assert!(!storage.chunks.has(blob.address()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn scrub_keeps_intact_chunk() -> Result<()> {
+        let mut storage = ChunkStorage {
+            chunks: ChunkStore::with_backend(MemoryBackend::new(), u64::MAX, false, None).await?,
+        };
+        let value = "immutable data value".to_owned().into_bytes();
+        let blob = Blob::Public(PublicBlob::new(value));
+        storage.try_store(&blob).await?;
+
+        assert!(!storage.scrub(blob.address()).await?);
+        assert!(storage.chunks.has(blob.address()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,197 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Handoffs of the chunks we are no longer a holder of to their new holders.
+//!
+//! A chunk handed off is kept until a majority of its new holders acknowledged storing it, and is
+//! sent again to the others in the meantime. The handoffs in progress are persisted under the root
+//! dir, so that they are resumed after a restart.
+
+use crate::{
+    encryption::{self, EncryptionKey},
+    utils, Result,
+};
+use serde::{Deserialize, Serialize};
+use sn_data_types::BlobAddress;
+use sn_routing::XorName;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub(crate) const HANDOFFS_FILENAME: &str = "chunk_handoffs";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Handoff {
+    // The new holders of the chunk.
+    targets: BTreeSet<XorName>,
+    // The new holders that acknowledged storing it.
+    acked: BTreeSet<XorName>,
+}
+
+impl Handoff {
+    fn is_complete(&self) -> bool {
+        !self.targets.is_empty() && self.acked.len() > self.targets.len() / 2
+    }
+}
+
+/// The handoffs in progress.
+pub(crate) struct ChunkHandoffs {
+    path: PathBuf,
+    encryption: Option<EncryptionKey>,
+    pending: BTreeMap<BlobAddress, Handoff>,
+    // When the chunk of each handoff was last sent, not persisted as all are due after a restart.
+    last_sent: BTreeMap<BlobAddress, Instant>,
+}
+
+impl ChunkHandoffs {
+    /// Loads the handoffs persisted under `root_dir`, if any.
+    pub fn load(root_dir: &Path, encryption: Option<EncryptionKey>) -> Result<Self> {
+        let path = root_dir.join(HANDOFFS_FILENAME);
+        let pending = if path.is_file() {
+            let contents = encryption::decrypt(encryption.as_ref(), fs::read(&path)?)?;
+            utils::deserialise(&contents)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path,
+            encryption,
+            pending,
+            last_sent: BTreeMap::new(),
+        })
+    }
+
+    /// Starts handing off the chunk at `address` to `targets`, just sent to them by us or another
+    /// holder, or retargets its handoff in progress, keeping the acks of the targets still among
+    /// them.
+    pub fn start(&mut self, address: BlobAddress, targets: BTreeSet<XorName>) -> Result<()> {
+        let handoff = self.pending.entry(address).or_default();
+        handoff.acked = handoff.acked.intersection(&targets).copied().collect();
+        handoff.targets = targets;
+        let _ = self.last_sent.insert(address, Instant::now());
+        self.write()
+    }
+
+    /// Stops handing off the chunk at `address`, e.g. as we are a holder of it again.
+    pub fn cancel(&mut self, address: &BlobAddress) -> Result<()> {
+        let _ = self.last_sent.remove(address);
+        if self.pending.remove(address).is_some() {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Records that `holder` acknowledged storing the chunk at `address`, returning whether its
+    /// handoff has completed, so that we no longer need to keep it.
+    pub fn confirm(&mut self, address: &BlobAddress, holder: XorName) -> Result<bool> {
+        let handoff = match self.pending.get_mut(address) {
+            Some(handoff) if handoff.targets.contains(&holder) => handoff,
+            _ => return Ok(false),
+        };
+        if !handoff.acked.insert(holder) {
+            return Ok(false);
+        }
+        let complete = handoff.is_complete();
+        if complete {
+            let _ = self.pending.remove(address);
+            let _ = self.last_sent.remove(address);
+        }
+        self.write()?;
+        Ok(complete)
+    }
+
+    /// Returns the handoffs not sent for `interval`, with the targets yet to acknowledge them,
+    /// recording them as sent now.
+    pub fn due(&mut self, interval: Duration) -> Vec<(BlobAddress, BTreeSet<XorName>)> {
+        let now = Instant::now();
+        let mut due = vec![];
+        for (address, handoff) in &self.pending {
+            let is_due = match self.last_sent.get(address) {
+                Some(sent) => now.duration_since(*sent) >= interval,
+                None => true,
+            };
+            if is_due {
+                let unacked = handoff
+                    .targets
+                    .difference(&handoff.acked)
+                    .copied()
+                    .collect();
+                due.push((*address, unacked));
+            }
+        }
+        for (address, _) in &due {
+            let _ = self.last_sent.insert(*address, now);
+        }
+        due
+    }
+
+    fn write(&self) -> Result<()> {
+        if self.pending.is_empty() {
+            if self.path.is_file() {
+                fs::remove_file(&self.path)?;
+            }
+            return Ok(());
+        }
+        let contents = encryption::encrypt(
+            self.encryption.as_ref(),
+            utils::serialise(&self.pending)?.to_vec(),
+        )?;
+        utils::write_atomically(&self.path, &contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChunkHandoffs;
+    use crate::{encryption::EncryptionKey, Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use sn_routing::XorName;
+    use std::{collections::BTreeSet, time::Duration};
+    use tempdir::TempDir;
+
+    #[test]
+    fn completes_on_majority_of_acks_across_restarts() -> Result<()> {
+        let root =
+            TempDir::new("handoff").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let encryption = EncryptionKey::random();
+        let address = *Blob::Public(PublicBlob::new(b"chunk".to_vec())).address();
+        let targets: Vec<_> = (0..4).map(|_| XorName::random()).collect();
+
+        let mut handoffs = ChunkHandoffs::load(root.path(), Some(encryption.clone()))?;
+        handoffs.start(address, targets.iter().copied().collect())?;
+        assert!(handoffs.due(Duration::from_secs(60)).is_empty());
+        assert!(!handoffs.confirm(&address, targets[0])?);
+        assert!(!handoffs.confirm(&address, XorName::random())?);
+        drop(handoffs);
+
+        // Resumed after a restart, and due right away.
+        let mut handoffs = ChunkHandoffs::load(root.path(), Some(encryption.clone()))?;
+        let due = handoffs.due(Duration::from_secs(60));
+        assert_eq!(due.len(), 1);
+        assert_eq!(
+            due[0].1,
+            targets[1..].iter().copied().collect::<BTreeSet<_>>()
+        );
+        assert!(handoffs.due(Duration::from_secs(60)).is_empty());
+
+        // Retargeting keeps the acks of the remaining targets.
+        let retargeted = [targets[0], targets[1], targets[2], XorName::random()];
+        handoffs.start(address, retargeted.iter().copied().collect())?;
+        assert!(!handoffs.confirm(&address, targets[0])?);
+        assert!(!handoffs.confirm(&address, targets[1])?);
+        assert!(handoffs.confirm(&address, targets[2])?);
+        assert!(!root.path().join("chunk_handoffs").exists());
+        assert!(ChunkHandoffs::load(root.path(), Some(encryption))?
+            .due(Duration::from_secs(0))
+            .is_empty());
+        Ok(())
+    }
+}
@@ -7,35 +7,79 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 mod chunk_storage;
+mod handoff;
 
 use crate::{
+    encryption::EncryptionKey,
     node_ops::{NodeDuties, NodeDuty},
     Result,
 };
 use chunk_storage::ChunkStorage;
-use log::info;
+use handoff::ChunkHandoffs;
+pub(crate) use handoff::HANDOFFS_FILENAME;
+use log::{info, warn};
 use sn_data_types::{Blob, BlobAddress, PublicKey};
 use sn_messaging::{
     client::{BlobRead, BlobWrite},
-    MessageId,
+    MessageId, SrcLocation,
 };
+use sn_routing::XorName;
 use std::{
+    collections::BTreeSet,
     fmt::{self, Display, Formatter},
     path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
 /// At 50% full, the node will report that it's reaching full capacity.
 pub const MAX_STORAGE_USAGE_RATIO: f64 = 0.5;
 
+/// Below 40% full, a node reported as full stops reporting it, for its Elders to no longer count it
+/// as full. The gap to `MAX_STORAGE_USAGE_RATIO` keeps it from flipping between the two on every
+/// write.
+pub const STORAGE_AVAILABLE_RATIO: f64 = 0.4;
+
+/// Interval between two reports of our storage full to our section while it is, for our Elders and
+/// the other Adults to keep counting us as full, see `capacity::FULL_ADULT_LEASE`.
+pub const STORAGE_FULL_RENEW_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between two rounds of chunk scrubbing.
+pub const CHUNK_SCRUB_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval after which a chunk handed off is sent again to the new holders yet to acknowledge it.
+pub const CHUNK_HANDOFF_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time a holder of a chunk waits for each holder elected before it to republish the chunk, before
+/// republishing it itself.
+pub const CHUNK_REPUBLISH_FALLBACK_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Operations on data chunks.
 pub(crate) struct Chunks {
     chunk_storage: ChunkStorage,
+    // Chunks still to be scrubbed in the current pass over the storage.
+    scrub_queue: Vec<BlobAddress>,
+    // Chunks quarantined as corrupt, yet to be restored from their other holders.
+    restoring: BTreeSet<BlobAddress>,
+    // Chunks we are no longer a holder of, kept until their new holders have them.
+    handoffs: ChunkHandoffs,
+    // Whether we are reporting our storage full.
+    full: AtomicBool,
 }
 
 impl Chunks {
-    pub async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
+    pub async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
         Ok(Self {
-            chunk_storage: ChunkStorage::new(path, max_capacity).await?,
+            handoffs: ChunkHandoffs::load(path, encryption.clone())?,
+            chunk_storage: ChunkStorage::new(path, max_capacity, compress, encryption).await?,
+            scrub_queue: vec![],
+            restoring: BTreeSet::new(),
+            full: AtomicBool::new(false),
         })
     }
 
@@ -51,6 +95,60 @@ impl Chunks {
         self.chunk_storage.get_chunk(address)
     }
 
+    /// Hands off the chunk at `address` to its new holders `targets`, to whom it was just sent by
+    /// us or another holder, keeping it until a majority of them acknowledged storing it to us.
+    pub fn hand_off(&mut self, address: BlobAddress, targets: BTreeSet<XorName>) -> Result<()> {
+        self.handoffs.start(address, targets)
+    }
+
+    /// Stops handing off the chunk at `address`, if we were, as we are a holder of it again.
+    pub fn cancel_handoff(&mut self, address: &BlobAddress) -> Result<()> {
+        self.handoffs.cancel(address)
+    }
+
+    /// Records that `holder` stored the chunk at `address` we are handing off, removing the chunk
+    /// once enough of its new holders did.
+    pub async fn confirm_handoff(
+        &mut self,
+        address: &BlobAddress,
+        holder: XorName,
+    ) -> Result<NodeDuties> {
+        if !self.handoffs.confirm(address, holder)? {
+            return Ok(vec![]);
+        }
+        info!("Handed off chunk {:?}, removing it", address);
+        self.remove_chunk(address).await?;
+        self.check_storage().await
+    }
+
+    /// Returns the chunks handed off that are due to be sent again, with the new holders yet to
+    /// acknowledge them.
+    pub fn due_handoffs(&mut self) -> Vec<(Blob, BTreeSet<XorName>)> {
+        let mut due = vec![];
+        for (address, targets) in self.handoffs.due(CHUNK_HANDOFF_RETRY_INTERVAL) {
+            match self.chunk_storage.get_chunk(&address) {
+                Ok(chunk) => due.push((chunk, targets)),
+                Err(error) => {
+                    warn!("Chunk {:?} lost while handing it off: {:?}", address, error);
+                    if let Err(error) = self.handoffs.cancel(&address) {
+                        warn!("Error cancelling handoff of {:?}: {:?}", address, error);
+                    }
+                }
+            }
+        }
+        due
+    }
+
+    pub fn get_for_republish(
+        &self,
+        address: &BlobAddress,
+        msg_id: MessageId,
+        origin: SrcLocation,
+    ) -> NodeDuty {
+        self.chunk_storage
+            .get_for_republish(address, msg_id, origin)
+    }
+
     pub fn read(&self, read: &BlobRead, msg_id: MessageId) -> NodeDuty {
         let BlobRead::Get(address) = read;
         self.chunk_storage.get(address, msg_id)
@@ -63,22 +161,69 @@ impl Chunks {
         requester: PublicKey,
     ) -> Result<NodeDuty> {
         match &write {
-            BlobWrite::New(data) => self.chunk_storage.store(&data).await,
+            BlobWrite::New(data) => Ok(self.chunk_storage.store(&data, msg_id).await),
             BlobWrite::DeletePrivate(address) => {
                 self.chunk_storage.delete(*address, msg_id, requester).await
             }
         }
     }
 
+    /// Reports `ReachingMaxCapacity` once the chunks use more than `MAX_STORAGE_USAGE_RATIO` of the
+    /// max capacity, or of the free space on disk if that is tighter, and then on every check until
+    /// they are back below `STORAGE_AVAILABLE_RATIO`, e.g. after deletions.
     pub async fn check_storage(&self) -> Result<NodeDuties> {
+        let used_space_ratio = self.chunk_storage.used_space_ratio().await;
         info!("Checking used storage");
-        if self.chunk_storage.used_space_ratio().await > MAX_STORAGE_USAGE_RATIO {
+        if used_space_ratio > MAX_STORAGE_USAGE_RATIO {
+            self.full.store(true, Ordering::SeqCst);
+        } else if used_space_ratio < STORAGE_AVAILABLE_RATIO {
+            self.full.store(false, Ordering::SeqCst);
+        }
+        if self.full.load(Ordering::SeqCst) {
             Ok(NodeDuties::from(NodeDuty::ReachingMaxCapacity))
         } else {
             Ok(vec![])
         }
     }
 
+    /// Re-validates the content hash of up to `count` chunks, continuing the current pass over
+    /// the storage, or starting a new one if the previous pass has completed.
+    ///
+    /// Returns the chunks to get a good copy of from their other holders: those found corrupt, and
+    /// when starting a new pass, those found corrupt before and not restored yet.
+    pub async fn scrub(&mut self, count: usize) -> Result<Vec<BlobAddress>> {
+        let mut corrupt = vec![];
+        if self.scrub_queue.is_empty() {
+            self.scrub_queue = self.chunk_storage.keys();
+            corrupt.extend(self.restoring.iter().copied());
+        }
+        for _ in 0..count {
+            let address = match self.scrub_queue.pop() {
+                Some(address) => address,
+                None => break,
+            };
+            if self.chunk_storage.scrub(&address).await? {
+                let _ = self.restoring.insert(address);
+                corrupt.push(address);
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Stores a good copy of a chunk we found corrupt, as got from its other holders. Returns
+    /// whether it was one, other chunks being left to the Elders to have stored.
+    pub async fn restore(&mut self, blob: Blob) -> Result<bool> {
+        if !self.restoring.contains(blob.address()) {
+            return Ok(false);
+        }
+        self.chunk_storage
+            .store_for_replication(blob.clone())
+            .await?;
+        let _ = self.restoring.remove(blob.address());
+        info!("Restored corrupt chunk {:?}", blob.address());
+        Ok(true)
+    }
+
     /// Stores a chunk that Elders sent to it for replication.
     pub async fn store_for_replication(&mut self, blob: Blob) -> Result<NodeDuty> {
         self.chunk_storage.store_for_replication(blob).await?;
@@ -91,3 +236,39 @@ impl Display for Chunks {
         write!(formatter, "Chunks")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Chunks;
+    use crate::{node_ops::NodeDuty, Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn reports_storage_full_and_available_again() -> Result<()> {
+        let root =
+            TempDir::new("chunks").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut chunks = Chunks::new(root.path(), 2000, false, None).await?;
+        assert!(chunks.check_storage().await?.is_empty());
+
+        let kept = Blob::Public(PublicBlob::new(vec![7; 850]));
+        let deleted = Blob::Public(PublicBlob::new(vec![7; 400]));
+        let _ = chunks.store_for_replication(kept.clone()).await?;
+        let _ = chunks.store_for_replication(deleted.clone()).await?;
+        assert!(matches!(
+            chunks.check_storage().await?.as_slice(),
+            [NodeDuty::ReachingMaxCapacity]
+        ));
+
+        // Reported full for as long as it is above the available ratio.
+        chunks.remove_chunk(deleted.address()).await?;
+        assert!(matches!(
+            chunks.check_storage().await?.as_slice(),
+            [NodeDuty::ReachingMaxCapacity]
+        ));
+
+        chunks.remove_chunk(kept.address()).await?;
+        assert!(chunks.check_storage().await?.is_empty());
+        Ok(())
+    }
+}
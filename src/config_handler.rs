@@ -18,6 +18,7 @@ use std::{
     io::{self, BufReader},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
+    str::FromStr,
 };
 use structopt::StructOpt;
 
@@ -25,6 +26,7 @@ const CONFIG_FILE: &str = "node.config";
 const CONNECTION_INFO_FILE: &str = "node_connection_info.config";
 const DEFAULT_ROOT_DIR_NAME: &str = "root_dir";
 const DEFAULT_MAX_CAPACITY: u64 = 2 * 1024 * 1024 * 1024;
+const DEFAULT_CHUNK_SCRUB_RATE: u32 = 5;
 
 /// Node configuration
 #[derive(Default, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, StructOpt)]
@@ -35,9 +37,41 @@ pub struct Config {
     /// A hex formatted BLS public key.
     #[structopt(short, long, parse(try_from_str))]
     pub wallet_id: Option<String>,
-    /// Upper limit in bytes for allowed network storage on this node.
+    /// Upper limit in bytes for allowed network storage on this node. Storage is also limited by
+    /// the free space left on the volume holding the root directory.
     #[structopt(short, long)]
     pub max_capacity: Option<u64>,
+    /// Number of stored chunks per second to re-validate against their content hash, to detect
+    /// disk corruption. If none supplied we'll default to the documented constant.
+    ///
+    /// A value of 0 disables this feature.
+    #[structopt(long)]
+    pub chunk_scrub_rate: Option<u32>,
+    /// Compress chunks when storing them. Chunks stored without compression remain readable.
+    #[structopt(long)]
+    pub chunk_compression: bool,
+    /// How to read chunks from their holders as an Elder: `hedged` queries the holder with the
+    /// best response times first, and the next ones if it is slow or fails to respond, while `all`
+    /// queries all the holders at once. This is a setting of each node, not of its section, as
+    /// sn_messaging has no message for Elders to agree one: each Elder reads with its own, so the
+    /// nodes of a section are to be run with the same one to compare sections. Defaults to `hedged`.
+    #[structopt(long)]
+    pub chunk_read_strategy: Option<ChunkReadStrategy>,
+    /// Upper limit in bytes for the public chunks kept in memory as an Elder, to serve reads of
+    /// popular chunks without querying their holders. If none supplied, or 0, no chunks are
+    /// cached.
+    #[structopt(long)]
+    pub chunk_cache_size: Option<u64>,
+    /// File holding the secret to derive the key to encrypt chunks, transfers and the node's keys
+    /// stored under the root directory with. Once stores have been encrypted, the same secret has to
+    /// be provided on every start.
+    #[structopt(long, parse(from_os_str))]
+    pub encryption_key_file: Option<PathBuf>,
+    /// Passphrase to derive the key to encrypt the node's stores with, as an alternative to
+    /// `--encryption-key-file`. It is never written to the config file.
+    #[structopt(long, env = "SN_NODE_ENCRYPTION_PASSPHRASE", hide_env_values = true)]
+    #[serde(skip)]
+    pub encryption_passphrase: Option<String>,
     /// Root directory for ChunkStores and cached state. If not set, it defaults to "root_dir"
     /// within the sn_node project data directory, located at:
     /// Linux: $HOME/.safe/node/root_dir
@@ -118,6 +152,60 @@ pub struct Config {
     #[structopt(skip)]
     #[allow(missing_docs)]
     pub network_config: NetworkConfig,
+    /// Command to run instead of starting the node.
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+}
+
+/// How Elders read chunks from their holders.
+///
+/// Each node is configured with its own strategy rather than agreeing on one with its section, as
+/// there is no message for Elders to share it with. The Elders of a section may thus read with
+/// different strategies, unless its nodes are all run with the same one.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChunkReadStrategy {
+    /// Query the holder with the best response times first, and the next ones in turn if it is
+    /// slow to respond, or all of them if it fails to.
+    Hedged,
+    /// Query all the holders at once.
+    All,
+}
+
+impl FromStr for ChunkReadStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "hedged" => Ok(Self::Hedged),
+            "all" => Ok(Self::All),
+            _ => Err(format!(
+                "Invalid chunk read strategy '{}', expected 'hedged' or 'all'",
+                value
+            )),
+        }
+    }
+}
+
+/// Commands run on the node's root directory instead of starting the node.
+#[derive(Clone, Debug, Eq, PartialEq, StructOpt)]
+pub enum Command {
+    /// Export the node's root directory to a portable archive, to move the node to another machine.
+    Export {
+        /// File to write the archive to.
+        #[structopt(long, parse(from_os_str))]
+        to: PathBuf,
+    },
+    /// Import the node's root directory from an archive written by `export`.
+    Import {
+        /// Archive to import.
+        #[structopt(long, parse(from_os_str))]
+        from: PathBuf,
+        /// Replace an existing non-empty root directory.
+        #[structopt(long)]
+        force: bool,
+    },
 }
 
 impl Config {
@@ -174,6 +262,28 @@ impl Config {
             self.max_capacity = Some(*max_capacity);
         }
 
+        if let Some(chunk_scrub_rate) = config.chunk_scrub_rate {
+            self.chunk_scrub_rate = Some(chunk_scrub_rate);
+        }
+
+        self.chunk_compression = config.chunk_compression || self.chunk_compression;
+
+        if let Some(chunk_read_strategy) = config.chunk_read_strategy {
+            self.chunk_read_strategy = Some(chunk_read_strategy);
+        }
+
+        if let Some(chunk_cache_size) = config.chunk_cache_size {
+            self.chunk_cache_size = Some(chunk_cache_size);
+        }
+
+        if let Some(encryption_key_file) = &config.encryption_key_file {
+            self.encryption_key_file = Some(encryption_key_file.clone());
+        }
+
+        if let Some(encryption_passphrase) = &config.encryption_passphrase {
+            self.encryption_passphrase = Some(encryption_passphrase.clone());
+        }
+
         if let Some(root_dir) = &config.root_dir {
             self.root_dir = Some(root_dir.clone());
         }
@@ -236,6 +346,10 @@ impl Config {
         if let Some(upnp_lease_duration) = config.upnp_lease_duration {
             self.network_config.upnp_lease_duration = Some(upnp_lease_duration);
         }
+
+        if let Some(command) = config.command {
+            self.command = Some(command);
+        }
     }
 
     /// The address to be credited when this node farms SafeCoin.
@@ -253,6 +367,42 @@ impl Config {
         self.max_capacity.unwrap_or(DEFAULT_MAX_CAPACITY)
     }
 
+    /// Number of stored chunks per second to re-validate against their content hash.
+    pub fn chunk_scrub_rate(&self) -> u32 {
+        self.chunk_scrub_rate.unwrap_or(DEFAULT_CHUNK_SCRUB_RATE)
+    }
+
+    /// Compress chunks when storing them?
+    pub fn chunk_compression(&self) -> bool {
+        self.chunk_compression
+    }
+
+    /// Upper limit in bytes for the public chunks cached as an Elder, 0 if none are.
+    pub fn chunk_cache_size(&self) -> u64 {
+        self.chunk_cache_size.unwrap_or(0)
+    }
+
+    /// How to read chunks from their holders as an Elder.
+    // TODO: agree the strategy through section state, once sn_messaging can carry it.
+    pub fn chunk_read_strategy(&self) -> ChunkReadStrategy {
+        self.chunk_read_strategy
+            .unwrap_or(ChunkReadStrategy::Hedged)
+    }
+
+    /// Secret to derive the at-rest encryption key from, read from the passphrase or the key file,
+    /// if either is set.
+    pub fn encryption_secret(&self) -> Result<Option<Vec<u8>>> {
+        match (&self.encryption_passphrase, &self.encryption_key_file) {
+            (Some(_), Some(_)) => Err(Error::Configuration(
+                "Only one of an encryption passphrase and an encryption key file can be set"
+                    .to_string(),
+            )),
+            (Some(passphrase), None) => Ok(Some(passphrase.as_bytes().to_vec())),
+            (None, Some(key_file)) => Ok(Some(fs::read(key_file)?)),
+            (None, None) => Ok(None),
+        }
+    }
+
     /// Root directory for `ChunkStore`s and cached state. If not set, it defaults to
     /// `DEFAULT_ROOT_DIR_NAME` within the project's data directory (see `Config::root_dir` for the
     /// directories on each platform).
@@ -314,6 +464,11 @@ impl Config {
         self.update_only
     }
 
+    /// Command to run instead of starting the node, if any.
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
     /// Set the Quic-P2P `ip` configuration to 127.0.0.1.
     pub fn listen_on_loopback(&mut self) {
         self.network_config.local_ip = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
@@ -428,7 +583,7 @@ fn smoke() {
     // NOTE: IF this value is being changed due to a change in the config,
     // the change in config also be handled in Config::merge()
     // and in examples/config_handling.rs
-    let expected_size = 504;
+    let expected_size = 600;
 
     assert_eq!(std::mem::size_of::<Config>(), expected_size);
 }
@@ -0,0 +1,305 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! At-rest encryption of the data a node keeps under its root directory.
+//!
+//! Encrypted data is stored behind a header made of `MAGIC` and a format byte, followed by a random
+//! nonce and the AES-256-GCM ciphertext. Anything else is taken to be plaintext, as stored before
+//! encryption was enabled. Such plaintext is encrypted in place on the next start, see
+//! `migrations`, after which it is refused.
+
+use crate::{utils, Error, Result};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    fs,
+    num::NonZeroU32,
+    path::Path,
+};
+
+/// Marks the start of a format header.
+const MAGIC: [u8; 4] = *b"SNEC";
+/// Format byte of data encrypted with AES-256-GCM.
+const FORMAT_AES_256_GCM: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// File in the root directory holding the salt the key is derived with.
+const SALT_FILENAME: &str = "encryption_salt";
+const SALT_LEN: usize = 16;
+/// File in the root directory holding `CHECK_PLAINTEXT` encrypted, to detect a wrong secret.
+const CHECK_FILENAME: &str = "encryption_check";
+const CHECK_PLAINTEXT: &[u8] = b"sn_node encryption check";
+/// File in the root directory marking that the data stored there before encryption was enabled
+/// has all been encrypted.
+const MIGRATED_FILENAME: &str = "encryption_migrated";
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Key the node-local stores are encrypted with.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    bytes: [u8; 32],
+    // Whether plaintext stored before encryption was enabled is still accepted.
+    accepts_plaintext: bool,
+}
+
+impl EncryptionKey {
+    /// Returns the key for the stores at `root_dir` derived from the operator's `secret` (a
+    /// passphrase or the contents of a key file), or `None` if no secret was configured.
+    ///
+    /// The salt to derive the key with is created on first use and kept in `root_dir`, along with
+    /// a check value to refuse a secret other than the one the stores were encrypted with. For the
+    /// same reason, stores that have been encrypted cannot be opened without a secret.
+    ///
+    /// Plaintext is accepted until the stores have all been encrypted, see `refuse_plaintext`.
+    pub fn load(root_dir: &Path, secret: Option<&[u8]>) -> Result<Option<Self>> {
+        let check_path = root_dir.join(CHECK_FILENAME);
+        let secret = match secret {
+            Some(secret) => secret,
+            None if check_path.is_file() => {
+                return Err(Error::Configuration(format!(
+                    "The stores at {} are encrypted, but no encryption secret was provided",
+                    root_dir.display()
+                )))
+            }
+            None => return Ok(None),
+        };
+
+        let salt_path = root_dir.join(SALT_FILENAME);
+        let salt = if salt_path.is_file() {
+            fs::read(&salt_path)?
+        } else {
+            let mut salt = vec![0; SALT_LEN];
+            SystemRandom::new()
+                .fill(&mut salt)
+                .map_err(|_| Error::Encryption("Could not generate a salt".to_string()))?;
+            fs::write(&salt_path, &salt)?;
+            salt
+        };
+
+        let mut key = Self::derive(secret, &salt)?;
+        key.accepts_plaintext = !root_dir.join(MIGRATED_FILENAME).is_file();
+        if check_path.is_file() {
+            let check = key
+                .decrypt(&fs::read(&check_path)?)
+                .map_err(|_| Error::Configuration("Wrong encryption secret".to_string()))?;
+            if check != CHECK_PLAINTEXT {
+                return Err(Error::Configuration("Wrong encryption secret".to_string()));
+            }
+        } else {
+            fs::write(&check_path, key.encrypt(CHECK_PLAINTEXT)?)?;
+        }
+
+        Ok(Some(key))
+    }
+
+    /// Tests if the stores at `root_dir` have been set up to be encrypted.
+    pub fn is_enabled(root_dir: &Path) -> bool {
+        root_dir.join(CHECK_FILENAME).is_file()
+    }
+
+    fn derive(secret: &[u8], salt: &[u8]) -> Result<Self> {
+        let mut bytes = [0; 32];
+        let iterations = NonZeroU32::new(PBKDF2_ITERATIONS)
+            .ok_or_else(|| Error::Logic("PBKDF2 iterations must not be zero".to_string()))?;
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            secret,
+            &mut bytes,
+        );
+        Ok(Self {
+            bytes,
+            accepts_plaintext: true,
+        })
+    }
+
+    /// Tests if plaintext, as stored before encryption was enabled, is still accepted.
+    pub(crate) fn accepts_plaintext(&self) -> bool {
+        self.accepts_plaintext
+    }
+
+    /// Records that the stores at `root_dir` have all been encrypted, and refuses plaintext from
+    /// then on, as it can only have been written by someone else.
+    pub(crate) fn refuse_plaintext(&mut self, root_dir: &Path) -> Result<()> {
+        utils::write_atomically(&root_dir.join(MIGRATED_FILENAME), &[])?;
+        self.accepts_plaintext = false;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn random() -> Self {
+        let mut bytes = [0; 32];
+        let _ = SystemRandom::new().fill(&mut bytes);
+        Self {
+            bytes,
+            accepts_plaintext: true,
+        }
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.bytes)
+            .map_err(|_| Error::Encryption("Invalid key length".to_string()))?;
+        Ok(LessSafeKey::new(key))
+    }
+
+    /// Returns `plaintext` encrypted, behind the format header.
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| Error::Encryption("Could not generate a nonce".to_string()))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.aead_key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| Error::Encryption("Could not encrypt data".to_string()))?;
+
+        let mut encrypted = MAGIC.to_vec();
+        encrypted.push(FORMAT_AES_256_GCM);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend(in_out);
+        Ok(encrypted)
+    }
+
+    /// Returns the plaintext of data returned by `encrypt`. Fails if the data was encrypted with
+    /// another key or has been tampered with.
+    pub(crate) fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>> {
+        if !is_encrypted(encrypted) || encrypted.len() < HEADER_LEN + NONCE_LEN {
+            return Err(Error::Encryption("Data is not encrypted".to_string()));
+        }
+        let (nonce, ciphertext) = encrypted[HEADER_LEN..].split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| Error::Encryption("Invalid nonce".to_string()))?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext_len = self
+            .aead_key()?
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| Error::Encryption("Could not decrypt data".to_string()))?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "EncryptionKey(..)")
+    }
+}
+
+/// Tests if `stored` starts with the header of encrypted data.
+pub(crate) fn is_encrypted(stored: &[u8]) -> bool {
+    stored.len() >= HEADER_LEN
+        && stored[..MAGIC.len()] == MAGIC
+        && stored[MAGIC.len()] == FORMAT_AES_256_GCM
+}
+
+/// Returns the bytes to store for `plaintext`: encrypted if there is a `key`, otherwise
+/// `plaintext` itself.
+pub(crate) fn encrypt(key: Option<&EncryptionKey>, plaintext: Vec<u8>) -> Result<Vec<u8>> {
+    match key {
+        Some(key) => key.encrypt(&plaintext),
+        None => Ok(plaintext),
+    }
+}
+
+/// Returns the plaintext of bytes returned by `encrypt`. Bytes stored unencrypted are returned as
+/// they are, unless the `key` refuses plaintext.
+pub(crate) fn decrypt(key: Option<&EncryptionKey>, stored: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_encrypted(&stored) {
+        return match key {
+            Some(key) if !key.accepts_plaintext => {
+                Err(Error::Encryption("Data is not encrypted".to_string()))
+            }
+            _ => Ok(stored),
+        };
+    }
+    match key {
+        Some(key) => key.decrypt(&stored),
+        None => Err(Error::Encryption(
+            "Data is encrypted, but no key was provided".to_string(),
+        )),
+    }
+}
+
+/// Encrypts the file at `path` in place if it holds plaintext. Returns whether it did, i.e. `false`
+/// if the file is already encrypted or doesn't exist.
+pub(crate) fn encrypt_file(path: &Path, key: &EncryptionKey) -> Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let contents = fs::read(path)?;
+    if is_encrypted(&contents) {
+        return Ok(false);
+    }
+    utils::write_atomically(path, &key.encrypt(&contents)?)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decrypt, encrypt, EncryptionKey};
+    use crate::{Error, Result};
+    use tempdir::TempDir;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let mut key = EncryptionKey::random();
+        let plaintext = b"some plaintext".to_vec();
+
+        let encrypted = encrypt(Some(&key), plaintext.clone())?;
+        assert_ne!(encrypted, plaintext);
+        assert_eq!(decrypt(Some(&key), encrypted.clone())?, plaintext);
+
+        // Wrong key, missing key and plaintext stored before encryption was enabled.
+        assert!(decrypt(Some(&EncryptionKey::random()), encrypted.clone()).is_err());
+        assert!(decrypt(None, encrypted.clone()).is_err());
+        assert_eq!(decrypt(Some(&key), plaintext.clone())?, plaintext);
+
+        // Plaintext is refused once the stores have all been encrypted.
+        let root =
+            TempDir::new("encryption").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        key.refuse_plaintext(root.path())?;
+        assert!(decrypt(Some(&key), plaintext.clone()).is_err());
+        assert_eq!(decrypt(Some(&key), encrypted)?, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    fn load_rejects_wrong_or_missing_secret() -> Result<()> {
+        let root =
+            TempDir::new("encryption").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+
+        assert!(EncryptionKey::load(root.path(), None)?.is_none());
+        let key = EncryptionKey::load(root.path(), Some(b"passphrase"))?;
+        let encrypted = encrypt(key.as_ref(), b"data".to_vec())?;
+
+        let reloaded = EncryptionKey::load(root.path(), Some(b"passphrase"))?;
+        assert_eq!(decrypt(reloaded.as_ref(), encrypted)?, b"data".to_vec());
+        assert!(EncryptionKey::load(root.path(), Some(b"other passphrase")).is_err());
+        assert!(EncryptionKey::load(root.path(), None).is_err());
+
+        let mut key = reloaded.ok_or_else(|| Error::Logic("No key".to_string()))?;
+        assert!(key.accepts_plaintext());
+        key.refuse_plaintext(root.path())?;
+        let reloaded = EncryptionKey::load(root.path(), Some(b"passphrase"))?;
+        assert!(matches!(reloaded, Some(key) if !key.accepts_plaintext()));
+        Ok(())
+    }
+}
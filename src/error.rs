@@ -169,6 +169,12 @@ pub enum Error {
     /// Logic error.
     #[error("Logic error: {0}")]
     Logic(String),
+    /// At-rest encryption error.
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    /// Node state archive error.
+    #[error("Archive error: {0}")]
+    Archive(String),
     /// Configuration error.
     #[error("Configuration error: {0}")]
     Configuration(String),
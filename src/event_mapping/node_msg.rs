@@ -15,8 +15,9 @@ use crate::{
 use log::debug;
 use sn_messaging::{
     node::{
-        NodeCmd, NodeDataQueryResponse, NodeMsg, NodeQuery, NodeQueryResponse, NodeRewardQuery,
-        NodeSystemCmd, NodeSystemQuery, NodeTransferCmd, NodeTransferQuery,
+        NodeCmd, NodeDataQueryResponse, NodeEvent, NodeMsg, NodeQuery, NodeQueryResponse,
+        NodeRewardQuery, NodeSystemCmd, NodeSystemQuery, NodeSystemQueryResponse, NodeTransferCmd,
+        NodeTransferQuery,
     },
     Aggregation, DstLocation, MessageId, SrcLocation,
 };
@@ -188,7 +189,18 @@ fn match_node_msg(msg: NodeMsg, origin: SrcLocation) -> NodeDuty {
         NodeMsg::NodeCmd {
             cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(data)),
             id,
-        } => NodeDuty::ReplicateChunk { data, msg_id: id },
+        } => NodeDuty::ReplicateChunk {
+            data,
+            msg_id: id,
+            origin,
+        },
+        NodeMsg::NodeEvent {
+            event: NodeEvent::ReplicationCompleted { chunk, .. },
+            ..
+        } => NodeDuty::ConfirmChunkHandoff {
+            address: chunk,
+            holder: origin.name(),
+        },
         NodeMsg::NodeCmd {
             cmd: NodeCmd::System(NodeSystemCmd::RepublishChunk(data)),
             id,
@@ -196,18 +208,43 @@ fn match_node_msg(msg: NodeMsg, origin: SrcLocation) -> NodeDuty {
             chunk: data,
             msg_id: id,
         },
+        NodeMsg::NodeQuery {
+            query: NodeQuery::System(NodeSystemQuery::GetChunk(address)),
+            id,
+        } => NodeDuty::GetChunkForRepublish {
+            address,
+            msg_id: id,
+            origin,
+        },
         // Aggregated by us, for security
         NodeMsg::NodeQuery {
             query: NodeQuery::System(NodeSystemQuery::GetSectionElders),
             id,
             ..
         } => NodeDuty::GetSectionElders { msg_id: id, origin },
+        NodeMsg::NodeQueryResponse {
+            response: NodeQueryResponse::System(NodeSystemQueryResponse::GetChunk(data)),
+            correlation_id,
+            ..
+        } => NodeDuty::ProcessRepublish {
+            chunk: data,
+            msg_id: correlation_id,
+        },
         //
         // ------ system cmd ------
         NodeMsg::NodeCmd {
             cmd: NodeCmd::System(NodeSystemCmd::StorageFull { node_id, .. }),
             ..
-        } => NodeDuty::IncrementFullNodeCount { node_id },
+        } => NodeDuty::IncrementFullNodeCount { node_id, origin },
+        NodeMsg::NodeEvent {
+            event: NodeEvent::ChunkWriteHandled(result),
+            correlation_id,
+            ..
+        } => NodeDuty::RecordAdultWriteLiveness {
+            result,
+            correlation_id,
+            src: origin.name(),
+        },
         //
         // ------ transfers ------
         NodeMsg::NodeQuery {
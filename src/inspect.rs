@@ -0,0 +1,308 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Offline inspection and repair of the stores under a node's root directory, as done by the
+//! `sn_node_store` tool.
+//!
+//! Nothing here needs the network, and apart from `StoreInspector::prune_temp_files`, nothing
+//! changes the stores on disk. The node should be stopped while its stores are inspected.
+
+use crate::{
+    chunk_store::{Chunk, ChunkStore, Subdir},
+    encryption::EncryptionKey,
+    to_db_key::{from_db_key, ToDbKey},
+    transfers::{replicas::wallet_key, store},
+    Error, Result,
+};
+use sn_data_types::{register::Register, Blob, Map, PublicKey, ReplicaEvent, Sequence};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use xor_name::XorName;
+
+/// The types of chunks a node stores, each in its own `ChunkStore`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// Blobs, stored at Adults.
+    Immutable,
+    /// Maps, stored at Elders.
+    Mutable,
+    /// Sequences, stored at Elders.
+    Sequence,
+    /// Registers, stored at Elders.
+    Register,
+}
+
+impl ChunkKind {
+    /// All the types of chunks.
+    pub const ALL: [ChunkKind; 4] = [
+        ChunkKind::Immutable,
+        ChunkKind::Mutable,
+        ChunkKind::Sequence,
+        ChunkKind::Register,
+    ];
+}
+
+impl Display for ChunkKind {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Immutable => "immutable",
+            Self::Mutable => "mutable",
+            Self::Sequence => "sequence",
+            Self::Register => "register",
+        };
+        write!(formatter, "{}", name)
+    }
+}
+
+impl FromStr for ChunkKind {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .find(|kind| kind.to_string() == name)
+            .copied()
+            .ok_or_else(|| Error::Logic(format!("Unknown chunk type: {}", name)))
+    }
+}
+
+/// Outcome of verifying the chunks of a `ChunkStore`.
+#[derive(Debug)]
+pub struct Verification {
+    /// Number of chunks checked.
+    pub chunks: usize,
+    /// Keys of the chunks not reading back as the chunk they are stored under.
+    pub corrupt: Vec<String>,
+    /// Total size in bytes of the chunks stored.
+    pub stored_size: u64,
+    /// Used space recorded for the store, if any.
+    pub recorded_used_space: Option<u64>,
+}
+
+impl Verification {
+    /// Tests if all chunks are intact and the used space record matches what is stored.
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+            && self.recorded_used_space.unwrap_or(self.stored_size) == self.stored_size
+    }
+}
+
+/// A wallet with events stored at the node.
+#[derive(Debug)]
+pub struct StoredWallet {
+    /// Name of the wallet's store, to pass to `StoreInspector::wallet_history`.
+    pub name: String,
+    /// Key of the wallet, unless its store is empty.
+    pub key: Option<PublicKey>,
+}
+
+/// Reads the stores under a node's root directory.
+pub struct StoreInspector {
+    root_dir: PathBuf,
+    encryption: Option<EncryptionKey>,
+}
+
+impl StoreInspector {
+    /// Opens the stores at `root_dir`, decrypting them with the key derived from `secret` if they
+    /// are encrypted.
+    pub fn new(root_dir: &Path, secret: Option<&[u8]>) -> Result<Self> {
+        if secret.is_some() && !EncryptionKey::is_enabled(root_dir) {
+            return Err(Error::Configuration(format!(
+                "The stores at {} are not encrypted",
+                root_dir.display()
+            )));
+        }
+        Ok(Self {
+            root_dir: root_dir.to_path_buf(),
+            encryption: EncryptionKey::load(root_dir, secret)?,
+        })
+    }
+
+    /// Lists the keys of the chunks of type `kind`.
+    pub async fn chunk_keys(&self, kind: ChunkKind) -> Result<Vec<String>> {
+        match kind {
+            ChunkKind::Immutable => self.keys::<Blob>().await,
+            ChunkKind::Mutable => self.keys::<Map>().await,
+            ChunkKind::Sequence => self.keys::<Sequence>().await,
+            ChunkKind::Register => self.keys::<Register>().await,
+        }
+    }
+
+    /// Returns the chunk of type `kind` stored under `key`, decoded and pretty printed.
+    pub async fn chunk(&self, kind: ChunkKind, key: &str) -> Result<String> {
+        match kind {
+            ChunkKind::Immutable => self.print::<Blob>(key).await,
+            ChunkKind::Mutable => self.print::<Map>(key).await,
+            ChunkKind::Sequence => self.print::<Sequence>(key).await,
+            ChunkKind::Register => self.print::<Register>(key).await,
+        }
+    }
+
+    /// Checks that the chunks of type `kind` are intact, and that the used space recorded for
+    /// their store matches their size.
+    pub async fn verify(&self, kind: ChunkKind) -> Result<Verification> {
+        match kind {
+            ChunkKind::Immutable => self.verify_store::<Blob>().await,
+            ChunkKind::Mutable => self.verify_store::<Map>().await,
+            ChunkKind::Sequence => self.verify_store::<Sequence>().await,
+            ChunkKind::Register => self.verify_store::<Register>().await,
+        }
+    }
+
+    /// Lists the wallets with events stored.
+    pub fn wallets(&self) -> Result<Vec<StoredWallet>> {
+        let mut wallets = vec![];
+        for id in store::stored_ids(&self.root_dir)? {
+            let key = self.read_wallet(id)?.and_then(|events| wallet_key(&events));
+            wallets.push(StoredWallet {
+                name: id.to_db_key()?,
+                key,
+            });
+        }
+        Ok(wallets)
+    }
+
+    /// Returns the events stored for the wallet `name`, as listed by `wallets`.
+    pub fn wallet_history(&self, name: &str) -> Result<Vec<ReplicaEvent>> {
+        self.read_wallet(from_db_key(name)?)?
+            .ok_or_else(|| Error::Logic(format!("No events stored for wallet {}", name)))
+    }
+
+    /// Removes the temp files left behind by writes interrupted by a crash, returning their paths.
+    pub async fn prune_temp_files(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = store::temp_files(&self.root_dir)?;
+        paths.extend(self.temp_files::<Blob>().await?);
+        paths.extend(self.temp_files::<Map>().await?);
+        paths.extend(self.temp_files::<Sequence>().await?);
+        paths.extend(self.temp_files::<Register>().await?);
+        for path in &paths {
+            fs::remove_file(path)?;
+        }
+        Ok(paths)
+    }
+
+    async fn open<T>(&self) -> Result<ChunkStore<T>>
+    where
+        T: Chunk,
+        ChunkStore<T>: Subdir,
+    {
+        ChunkStore::open_read_only(&self.root_dir, self.encryption.clone()).await
+    }
+
+    async fn keys<T>(&self) -> Result<Vec<String>>
+    where
+        T: Chunk,
+        ChunkStore<T>: Subdir,
+    {
+        self.open::<T>()
+            .await?
+            .keys()
+            .iter()
+            .map(ToDbKey::to_db_key)
+            .collect()
+    }
+
+    async fn print<T>(&self, key: &str) -> Result<String>
+    where
+        T: Chunk + Debug,
+        ChunkStore<T>: Subdir,
+    {
+        let chunk = self.open::<T>().await?.get(&from_db_key(key)?)?;
+        Ok(format!("{:#?}", chunk))
+    }
+
+    async fn verify_store<T>(&self) -> Result<Verification>
+    where
+        T: Chunk,
+        ChunkStore<T>: Subdir,
+    {
+        let store = self.open::<T>().await?;
+        let keys = store.keys();
+        let mut corrupt = vec![];
+        for key in &keys {
+            if !store.is_intact(key)? {
+                corrupt.push(key.to_db_key()?);
+            }
+        }
+        Ok(Verification {
+            chunks: keys.len(),
+            corrupt,
+            stored_size: store.stored_size(),
+            recorded_used_space: store.recorded_used_space()?,
+        })
+    }
+
+    async fn temp_files<T>(&self) -> Result<Vec<PathBuf>>
+    where
+        T: Chunk,
+        ChunkStore<T>: Subdir,
+    {
+        match self.open::<T>().await {
+            Ok(store) => Ok(store.temp_files()),
+            // No such store, so nothing to prune.
+            Err(Error::Logic(_)) => Ok(vec![]),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read_wallet(&self, id: XorName) -> Result<Option<Vec<ReplicaEvent>>> {
+        store::read_stored(id, &self.root_dir, self.encryption.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChunkKind, StoreInspector};
+    use crate::{chunk_store::BlobChunkStore, Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[tokio::test]
+    async fn verifies_and_prunes_chunk_store() -> Result<()> {
+        let root =
+            TempDir::new("inspect").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let mut chunk_store = BlobChunkStore::new(root.path(), u64::MAX, false, None).await?;
+        for value in &[b"first".to_vec(), b"second".to_vec()] {
+            chunk_store
+                .put(&Blob::Public(PublicBlob::new(value.clone())))
+                .await?;
+        }
+        drop(chunk_store);
+
+        let inspector = StoreInspector::new(root.path(), None)?;
+        let keys = inspector.chunk_keys(ChunkKind::Immutable).await?;
+        assert_eq!(keys.len(), 2);
+        assert!(inspector
+            .chunk(ChunkKind::Immutable, &keys[0])
+            .await?
+            .contains("Public"));
+        let verification = inspector.verify(ChunkKind::Immutable).await?;
+        assert_eq!(verification.chunks, 2);
+        assert!(verification.is_ok());
+        assert!(inspector.verify(ChunkKind::Mutable).await.is_err());
+
+        // A temp file left behind by a crash, in the shard dir of a chunk.
+        let shard_dir = root.path().join("chunks").join("immutable");
+        let leaf_dir = fs::read_dir(&shard_dir)?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.path().is_dir())
+            .and_then(|entry| fs::read_dir(entry.path()).ok()?.next()?.ok())
+            .ok_or_else(|| Error::Logic("No shard dir".to_string()))?;
+        let temp_file = leaf_dir.path().join("0123.tmp");
+        fs::write(&temp_file, b"torn")?;
+
+        assert_eq!(inspector.prune_temp_files().await?, vec![temp_file.clone()]);
+        assert!(!temp_file.exists());
+        Ok(())
+    }
+}
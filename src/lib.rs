@@ -34,9 +34,11 @@ mod chaos;
 mod chunk_store;
 mod chunks;
 mod config_handler;
+mod encryption;
 mod error;
 mod event_mapping;
 mod metadata;
+mod migrations;
 mod network;
 mod node;
 mod node_ops;
@@ -47,10 +49,17 @@ mod transfers;
 /// Docs
 pub mod state_db;
 
+pub mod inspect;
+
+pub mod archive;
+
 pub mod utils;
 
 pub use crate::{
-    config_handler::{add_connection_info, set_connection_info, Config},
+    config_handler::{
+        add_connection_info, set_connection_info, ChunkReadStrategy, Command, Config,
+    },
+    encryption::EncryptionKey,
     error::{Error, Result},
     node::Node,
 };
@@ -1,5 +1,3 @@
-use std::collections::{BTreeSet, HashMap};
-
 // Copyright 2021 MaidSafe.net limited.
 //
 // This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
@@ -8,42 +6,182 @@ use std::collections::{BTreeSet, HashMap};
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+//! Liveness of the Adults we send reads and chunk writes to.
+//!
+//! Every read an Adult does not respond to within `READ_DEADLINE`, and every write it does not
+//! acknowledge within `WRITE_DEADLINE`, adds a penalty to it, which decays by half every
+//! `PENALTY_HALF_LIFE`, so that only sustained, recent unresponsiveness counts against it. The
+//! response times of the Adults are tracked as well. The penalties and response times are
+//! persisted under the root dir, so that they survive a restart.
+//!
+//! Reads may query the holders of a chunk one at a time, the one with the best response times
+//! first, and the next one whenever the ones queried are slow to respond, or all the others at once
+//! if one fails to.
+//!
+//! Writes not acknowledged as stored by all their holders in time are retried to alternate Adults,
+//! up to `MAX_WRITE_ATTEMPTS` times.
+
+use crate::{
+    encryption::{self, EncryptionKey},
+    utils, Result,
+};
 use itertools::Itertools;
-use sn_data_types::BlobAddress;
-use sn_messaging::{EndUser, MessageId};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{Blob, BlobAddress};
+use sn_messaging::{client::ClientSigned, EndUser, MessageId};
 use sn_routing::XorName;
-use std::collections::hash_map::Entry;
-
-use crate::capacity::CHUNK_COPY_COUNT;
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
+pub(crate) const LIVENESS_FILENAME: &str = "adult_liveness";
 const NEIGHBOUR_COUNT: usize = 2;
-const MIN_PENDING_OPS: usize = 10;
-const PENDING_OP_TOLERANCE_RATIO: f64 = 0.1;
+// The decayed penalty an Adult must be over to be deemed unresponsive.
+const MIN_PENALTY: f64 = 10.0;
+const PENALTY_TOLERANCE_RATIO: f64 = 0.1;
+// Time for a penalty to decay to half of its weight.
+const PENALTY_HALF_LIFE: Duration = Duration::from_secs(10 * 60);
+// Weight of the latest response time in the average response time of an Adult.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// Time an Adult has to respond to a read before being penalised.
+const READ_DEADLINE: Duration = Duration::from_secs(10);
+/// Time an Adult has to acknowledge storing a chunk before being penalised.
+const WRITE_DEADLINE: Duration = Duration::from_secs(20);
+/// Times a chunk is sent for storing, to its holders and then to alternate Adults.
+const MAX_WRITE_ATTEMPTS: usize = 3;
+// Bounds of the time to wait for a holder to respond to a read before querying the next one.
+const MIN_HEDGE_DELAY: Duration = Duration::from_millis(200);
+const MAX_HEDGE_DELAY: Duration = Duration::from_secs(2);
+// Multiple of its average response time to wait for a holder to respond to a read.
+const HEDGE_LATENCY_FACTOR: f64 = 3.0;
+/// Interval between two checks of the liveness of our Adults.
+pub const ADULT_LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Clone, Debug)]
 struct ReadOperation {
     address: BlobAddress,
     origin: EndUser,
-    targets: BTreeSet<XorName>,
+    // The holders queried and yet to respond, with when they were queried.
+    targets: BTreeMap<XorName, Instant>,
+    // The holders yet to query, in order, if the ones queried are slow or fail to respond.
+    remaining: Vec<XorName>,
     responded_with_success: bool,
 }
 
+/// What to do with the response of an Adult to a read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReadProgress {
+    /// Forward it to the client.
+    Respond(BlobAddress, EndUser),
+    /// Query the holders not queried yet instead of forwarding the error it is.
+    FallBack(BlobAddress, EndUser, BTreeSet<XorName>),
+    /// Drop it, as the client already got a response.
+    Drop,
+}
+
+#[derive(Clone, Debug)]
+struct WriteOperation {
+    data: Blob,
+    client_signed: ClientSigned,
+    origin: EndUser,
+    // The holders yet to acknowledge storing the chunk.
+    targets: BTreeSet<XorName>,
+    // The number of holders that failed to store it, or left.
+    failed: usize,
+    // All the Adults the chunk was sent to.
+    tried: BTreeSet<XorName>,
+    attempts: usize,
+    sent: Instant,
+}
+
+/// A write to retry, as some of its holders did not store the chunk in time.
+#[derive(Clone, Debug)]
+pub struct WriteRetry {
+    pub msg_id: MessageId,
+    pub data: Blob,
+    pub client_signed: ClientSigned,
+    pub origin: EndUser,
+    /// The number of holders to replace.
+    pub count: usize,
+    /// The Adults already sent the chunk, not to be sent it again.
+    pub tried: BTreeSet<XorName>,
+    attempts: usize,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct AdultRecord {
+    // The penalty for the reads timed out, as of `updated`.
+    penalty: f64,
+    updated: SystemTime,
+    // The average response time, in seconds.
+    latency: Option<f64>,
+}
+
+impl AdultRecord {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            penalty: 0.0,
+            updated: now,
+            latency: None,
+        }
+    }
+
+    fn penalty_at(&self, now: SystemTime) -> f64 {
+        let elapsed = now.duration_since(self.updated).unwrap_or_default();
+        self.penalty * 0.5_f64.powf(elapsed.as_secs_f64() / PENALTY_HALF_LIFE.as_secs_f64())
+    }
+
+    fn penalise(&mut self, now: SystemTime) {
+        self.penalty = self.penalty_at(now) + 1.0;
+        self.updated = now;
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        self.latency = Some(match self.latency {
+            Some(average) => average + LATENCY_WEIGHT * (latency - average),
+            None => latency,
+        });
+    }
+}
+
 pub struct AdultLiveness {
+    path: PathBuf,
+    encryption: Option<EncryptionKey>,
     ops: HashMap<MessageId, ReadOperation>,
-    pending_ops: HashMap<XorName, usize>,
-    closest_adults: HashMap<XorName, Vec<XorName>>,
+    writes: HashMap<MessageId, WriteOperation>,
+    adults: BTreeMap<XorName, AdultRecord>,
+    // Whether `adults` changed since it was last written.
+    changed: bool,
 }
 
 impl AdultLiveness {
-    pub fn new() -> Self {
-        Self {
+    /// Loads the liveness persisted under `root_dir`, if any.
+    pub fn load(root_dir: &Path, encryption: Option<EncryptionKey>) -> Result<Self> {
+        let path = root_dir.join(LIVENESS_FILENAME);
+        let adults = if path.is_file() {
+            let contents = encryption::decrypt(encryption.as_ref(), fs::read(&path)?)?;
+            utils::deserialise(&contents)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path,
+            encryption,
             ops: HashMap::default(),
-            pending_ops: HashMap::default(),
-            closest_adults: HashMap::default(),
-        }
+            writes: HashMap::default(),
+            adults,
+            changed: false,
+        })
     }
 
-    // Inserts a new read operation
+    // Inserts a new read operation, querying `targets`
+    // and then `remaining` in turn if they are slow to respond.
     // Returns false if the operation already existed.
     pub fn new_read(
         &mut self,
@@ -51,57 +189,171 @@ impl AdultLiveness {
         address: BlobAddress,
         origin: EndUser,
         targets: BTreeSet<XorName>,
+        remaining: Vec<XorName>,
     ) -> bool {
-        let new_operation = if let Entry::Vacant(entry) = self.ops.entry(msg_id) {
-            let _ = entry.insert(ReadOperation {
-                address,
-                origin,
-                targets: targets.clone(),
-                responded_with_success: false,
-            });
-            true
-        } else {
-            false
+        let entry = match self.ops.entry(msg_id) {
+            Entry::Vacant(entry) => entry,
+            Entry::Occupied(_) => return false,
         };
-        if new_operation {
-            self.increment_pending_op(&targets);
+        let now = Instant::now();
+        let _ = entry.insert(ReadOperation {
+            address,
+            origin,
+            targets: targets.iter().map(|target| (*target, now)).collect(),
+            remaining: remaining.clone(),
+            responded_with_success: false,
+        });
+        self.track(&targets);
+        self.track(&remaining.into_iter().collect());
+        true
+    }
+
+    /// Returns the `holders` of the chunk named `name` in the order to query them: the one with
+    /// the best response times first, and the others by their distance to it.
+    ///
+    /// Holders that recently failed to respond in time are not put first.
+    pub fn holders_by_preference(
+        &self,
+        name: &XorName,
+        holders: &BTreeSet<XorName>,
+    ) -> Vec<XorName> {
+        let now = SystemTime::now();
+        let mut ordered = holders
+            .iter()
+            .sorted_by(|lhs, rhs| name.cmp_distance(lhs, rhs))
+            .copied()
+            .collect::<Vec<_>>();
+        let fastest = ordered
+            .iter()
+            .enumerate()
+            .filter_map(|(index, holder)| {
+                let record = self.adults.get(holder)?;
+                if record.penalty_at(now) >= 1.0 {
+                    return None;
+                }
+                Some((index, record.latency?))
+            })
+            .min_by(|(_, lhs), (_, rhs)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal))
+            .map(|(index, _)| index);
+        if let Some(index) = fastest {
+            let holder = ordered.remove(index);
+            ordered.insert(0, holder);
+        }
+        ordered
+    }
+
+    /// Returns how long to wait for `holder` to respond before querying the next holder: a few
+    /// times its average response time, within bounds.
+    pub fn hedge_delay(&self, holder: &XorName) -> Duration {
+        match self.adults.get(holder).and_then(|record| record.latency) {
+            Some(latency) => Duration::from_secs_f64(latency * HEDGE_LATENCY_FACTOR)
+                .max(MIN_HEDGE_DELAY)
+                .min(MAX_HEDGE_DELAY),
+            None => MAX_HEDGE_DELAY,
+        }
+    }
+
+    /// Queries the next holder for the read `msg_id`, unless it got a successful response
+    /// already, returning the chunk address, the client and the holder.
+    pub fn hedge_read(&mut self, msg_id: MessageId) -> Option<(BlobAddress, EndUser, XorName)> {
+        let op = self.ops.get_mut(&msg_id)?;
+        if op.responded_with_success || op.remaining.is_empty() {
+            return None;
+        }
+        let holder = op.remaining.remove(0);
+        let _ = op.targets.insert(holder, Instant::now());
+        Some((op.address, op.origin, holder))
+    }
+
+    // Inserts a new write of a chunk to its holders.
+    // Returns false if the operation already existed.
+    pub fn new_write(
+        &mut self,
+        msg_id: MessageId,
+        data: Blob,
+        client_signed: ClientSigned,
+        origin: EndUser,
+        targets: BTreeSet<XorName>,
+    ) -> bool {
+        if self.writes.contains_key(&msg_id) {
+            return false;
+        }
+        let write = WriteOperation {
+            data,
+            client_signed,
+            origin,
+            targets,
+            failed: 0,
+            tried: BTreeSet::new(),
+            attempts: 1,
+            sent: Instant::now(),
+        };
+        self.insert_write(msg_id, write);
+        true
+    }
+
+    /// Tracks the write being retried to `targets`.
+    pub fn retry_write(&mut self, retry: WriteRetry, targets: BTreeSet<XorName>) {
+        let write = WriteOperation {
+            data: retry.data,
+            client_signed: retry.client_signed,
+            origin: retry.origin,
+            targets,
+            failed: 0,
+            tried: retry.tried,
+            attempts: retry.attempts + 1,
+            sent: Instant::now(),
+        };
+        self.insert_write(retry.msg_id, write);
+    }
+
+    fn insert_write(&mut self, msg_id: MessageId, mut write: WriteOperation) {
+        write.tried.extend(write.targets.iter().copied());
+        self.track(&write.targets);
+        let _ = self.writes.insert(msg_id, write);
+    }
+
+    // Starts tracking the Adults not tracked yet.
+    fn track(&mut self, adults: &BTreeSet<XorName>) {
+        let now = SystemTime::now();
+        for adult in adults {
+            if !self.adults.contains_key(adult) {
+                let _ = self.adults.insert(*adult, AdultRecord::new(now));
+                self.changed = true;
+            }
         }
-        new_operation
     }
 
     pub fn retain_members_only(&mut self, current_members: BTreeSet<XorName>) {
-        let old_members = self.closest_adults.keys().cloned().collect::<Vec<_>>();
+        let old_members = self.adults.keys().copied().collect::<Vec<_>>();
         for name in old_members {
             if !current_members.contains(&name) {
-                let _ = self.pending_ops.remove(&name);
-                let _ = self.closest_adults.remove(&name);
+                let _ = self.adults.remove(&name);
+                self.changed = true;
                 let message_ids = self.ops.keys().cloned().collect::<Vec<_>>();
-                // TODO(after T4): For write operations perhaps we need to write it to a different Adult
                 for msg_id in message_ids {
-                    self.remove_target(msg_id, &name);
+                    let _ = self.remove_target(msg_id, &name);
+                }
+                // Writes to it are retried to a different Adult.
+                for write in self.writes.values_mut() {
+                    if write.targets.remove(&name) {
+                        write.failed += 1;
+                    }
                 }
             }
         }
-        self.recompute_closest_adults();
     }
 
-    pub fn remove_target(&mut self, msg_id: MessageId, name: &XorName) {
-        if let Some(count) = self.pending_ops.get_mut(name) {
-            let counter = *count;
-            if counter > 0 {
-                *count -= 1;
-            }
-        }
-        let complete = if let Some(operation) = self.ops.get_mut(&msg_id) {
-            let ReadOperation { targets, .. } = operation;
-            let _ = targets.remove(name);
-            targets.is_empty()
-        } else {
-            true
-        };
-        if complete {
+    // Removes `name` from the targets and remaining holders of the operation, returning when it
+    // was queried if it was yet to respond. The operation is removed once no holder is left.
+    fn remove_target(&mut self, msg_id: MessageId, name: &XorName) -> Option<Instant> {
+        let op = self.ops.get_mut(&msg_id)?;
+        let queried = op.targets.remove(name);
+        op.remaining.retain(|holder| holder != name);
+        if op.targets.is_empty() && op.remaining.is_empty() {
             let _ = self.ops.remove(&msg_id);
         }
+        queried
     }
 
     pub fn record_adult_read_liveness(
@@ -109,81 +361,425 @@ impl AdultLiveness {
         correlation_id: MessageId,
         src: &XorName,
         success: bool,
-    ) -> Option<(BlobAddress, EndUser)> {
-        self.remove_target(correlation_id, src);
-        let op = self.ops.get_mut(&correlation_id);
-        op.and_then(|op| {
-            let ReadOperation {
-                address,
-                origin,
-                targets,
-                responded_with_success,
-            } = op;
-
-            if targets.len() < CHUNK_COPY_COUNT && *responded_with_success {
-                None
-            } else {
-                *responded_with_success = success;
-                Some((*address, *origin))
+    ) -> ReadProgress {
+        let op = match self.ops.get_mut(&correlation_id) {
+            Some(op) => op,
+            None => return ReadProgress::Drop,
+        };
+        let queried = match op.targets.remove(src) {
+            Some(queried) => queried,
+            None => return ReadProgress::Drop,
+        };
+        let progress = if op.responded_with_success {
+            ReadProgress::Drop
+        } else if !success && !op.remaining.is_empty() {
+            let now = Instant::now();
+            let fallback = op.remaining.drain(..).collect::<BTreeSet<_>>();
+            op.targets
+                .extend(fallback.iter().map(|holder| (*holder, now)));
+            ReadProgress::FallBack(op.address, op.origin, fallback)
+        } else if !success && !op.targets.is_empty() {
+            // Other holders may still succeed, so only the last failure is responded.
+            ReadProgress::Drop
+        } else {
+            op.responded_with_success = success;
+            if success {
+                op.remaining.clear();
             }
-        })
+            ReadProgress::Respond(op.address, op.origin)
+        };
+        if op.targets.is_empty() && op.remaining.is_empty() {
+            let _ = self.ops.remove(&correlation_id);
+        }
+        if let Some(record) = self.adults.get_mut(src) {
+            record.record_latency(queried.elapsed());
+            self.changed = true;
+        }
+        progress
+    }
+
+    /// Records the ack of `src` for storing the chunk of the write `correlation_id`.
+    pub fn record_adult_write_liveness(
+        &mut self,
+        correlation_id: MessageId,
+        src: &XorName,
+        success: bool,
+    ) {
+        let write = match self.writes.get_mut(&correlation_id) {
+            Some(write) => write,
+            None => return,
+        };
+        if !write.targets.remove(src) {
+            return;
+        }
+        if !success {
+            write.failed += 1;
+        }
+        let latency = write.sent.elapsed();
+        if write.targets.is_empty() && write.failed == 0 {
+            let _ = self.writes.remove(&correlation_id);
+        }
+        if let Some(record) = self.adults.get_mut(src) {
+            record.record_latency(latency);
+            self.changed = true;
+        }
+    }
+
+    /// Penalises the Adults for the writes they did not acknowledge in time, and returns the
+    /// writes to retry to alternate Adults.
+    pub fn expire_writes(&mut self) -> Vec<WriteRetry> {
+        self.expire_writes_at(Instant::now(), SystemTime::now())
     }
 
-    fn increment_pending_op(&mut self, targets: &BTreeSet<XorName>) {
-        for node in targets {
-            *self.pending_ops.entry(*node).or_insert(0) += 1;
-            if !self.closest_adults.contains_key(node) {
-                let _ = self.closest_adults.insert(*node, Vec::new());
-                self.recompute_closest_adults();
+    fn expire_writes_at(&mut self, now: Instant, time: SystemTime) -> Vec<WriteRetry> {
+        let expired = self
+            .writes
+            .iter()
+            .filter(|(_, write)| now.saturating_duration_since(write.sent) >= WRITE_DEADLINE)
+            .map(|(msg_id, _)| *msg_id)
+            .collect::<Vec<_>>();
+        let mut retries = vec![];
+        for msg_id in expired {
+            let write = match self.writes.remove(&msg_id) {
+                Some(write) => write,
+                None => continue,
+            };
+            for name in &write.targets {
+                if let Some(record) = self.adults.get_mut(name) {
+                    record.penalise(time);
+                    self.changed = true;
+                }
+            }
+            let count = write.targets.len() + write.failed;
+            if count == 0 {
+                continue;
+            }
+            if write.attempts >= MAX_WRITE_ATTEMPTS {
+                log::warn!(
+                    "Giving up on storing {} more copies of chunk {:?}",
+                    count,
+                    write.data.address()
+                );
+                continue;
             }
+            retries.push(WriteRetry {
+                msg_id,
+                data: write.data,
+                client_signed: write.client_signed,
+                origin: write.origin,
+                count,
+                tried: write.tried,
+                attempts: write.attempts,
+            });
+        }
+        retries
+    }
+
+    /// Penalises the Adults for the reads they did not respond to in time, persists the liveness
+    /// if it changed, and returns the Adults deemed unresponsive, with their penalty.
+    pub fn check(&mut self) -> Result<Vec<(XorName, f64)>> {
+        let now = SystemTime::now();
+        self.expire_reads(Instant::now(), now);
+        if self.changed {
+            self.write()?;
+            self.changed = false;
         }
+        Ok(self.find_unresponsive_adults(now))
     }
 
-    pub fn recompute_closest_adults(&mut self) {
-        let closest_adults_collection = self
-            .closest_adults
+    fn expire_reads(&mut self, now: Instant, time: SystemTime) {
+        let expired = self
+            .ops
             .iter()
-            .map(|(key, _)| {
-                let closest_adults = self
-                    .closest_adults
-                    .keys()
-                    .filter(|name| key != *name)
-                    .sorted_by(|lhs, rhs| key.cmp_distance(lhs, rhs))
-                    .take(NEIGHBOUR_COUNT)
-                    .copied()
-                    .collect::<Vec<_>>();
-
-                (key.to_owned(), closest_adults)
+            .flat_map(|(msg_id, op)| {
+                op.targets
+                    .iter()
+                    .filter(|(_, queried)| {
+                        now.saturating_duration_since(**queried) >= READ_DEADLINE
+                    })
+                    .map(move |(name, _)| (*msg_id, *name))
             })
-            .collect::<Vec<(XorName, Vec<XorName>)>>();
-
-        self.closest_adults.extend(closest_adults_collection);
+            .collect::<Vec<_>>();
+        for (msg_id, name) in expired {
+            if let Some(record) = self.adults.get_mut(&name) {
+                record.penalise(time);
+                self.changed = true;
+            }
+            // The next holders are to be queried by now, unless the read has completed.
+            let _ = self.remove_target(msg_id, &name);
+        }
     }
 
-    pub fn find_unresponsive_adults(&self) -> Vec<(XorName, usize)> {
+    fn find_unresponsive_adults(&self, now: SystemTime) -> Vec<(XorName, f64)> {
         let mut unresponsive_adults = Vec::new();
-        for (adult, neighbours) in &self.closest_adults {
-            if let Some(max_pending_by_neighbours) = neighbours
+        for (adult, record) in &self.adults {
+            let max_penalty_of_neighbours = self
+                .adults
                 .iter()
-                .map(|neighbour| self.pending_ops.get(neighbour).unwrap_or(&0))
-                .max()
-            {
-                let adult_pending_ops = *self.pending_ops.get(adult).unwrap_or(&0);
-                if adult_pending_ops > MIN_PENDING_OPS
-                    && max_pending_by_neighbours > &MIN_PENDING_OPS
-                    && adult_pending_ops as f64 * PENDING_OP_TOLERANCE_RATIO
-                        > *max_pending_by_neighbours as f64
+                .filter(|(name, _)| *name != adult)
+                .sorted_by(|(lhs, _), (rhs, _)| adult.cmp_distance(lhs, rhs))
+                .take(NEIGHBOUR_COUNT)
+                .map(|(_, neighbour)| neighbour.penalty_at(now))
+                .fold(None, |max: Option<f64>, penalty| {
+                    Some(max.map_or(penalty, |max| max.max(penalty)))
+                });
+            if let Some(max_penalty_of_neighbours) = max_penalty_of_neighbours {
+                let penalty = record.penalty_at(now);
+                if penalty > MIN_PENALTY
+                    && penalty * PENALTY_TOLERANCE_RATIO > max_penalty_of_neighbours
                 {
                     log::info!(
-                        "Pending ops for {}: {} Neighbour max: {}",
+                        "Penalty of {}: {:.1} Neighbour max: {:.1} Response time: {:?}",
                         adult,
-                        adult_pending_ops,
-                        max_pending_by_neighbours
+                        penalty,
+                        max_penalty_of_neighbours,
+                        record.latency.map(Duration::from_secs_f64),
                     );
-                    unresponsive_adults.push((*adult, adult_pending_ops));
+                    unresponsive_adults.push((*adult, penalty));
                 }
             }
         }
         unresponsive_adults
     }
+
+    fn write(&self) -> Result<()> {
+        let contents = encryption::encrypt(
+            self.encryption.as_ref(),
+            utils::serialise(&self.adults)?.to_vec(),
+        )?;
+        utils::write_atomically(&self.path, &contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AdultLiveness, ReadProgress, MAX_HEDGE_DELAY, MAX_WRITE_ATTEMPTS, PENALTY_HALF_LIFE,
+        READ_DEADLINE, WRITE_DEADLINE,
+    };
+    use crate::{btree_set, encryption::EncryptionKey, Error, Result};
+    use bls::SecretKey;
+    use itertools::Itertools;
+    use sn_data_types::{Blob, PublicBlob};
+    use sn_data_types::{PublicKey, Signature};
+    use sn_messaging::{client::ClientSigned, EndUser, MessageId};
+    use sn_routing::XorName;
+    use std::{
+        collections::BTreeSet,
+        time::{Instant, SystemTime},
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn flags_recent_sustained_timeouts_across_restarts() -> Result<()> {
+        let root =
+            TempDir::new("liveness").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let encryption = EncryptionKey::random();
+        let address = *Blob::Public(PublicBlob::new(b"chunk".to_vec())).address();
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let adults: Vec<_> = (0..3).map(|_| XorName::random()).collect();
+        let targets: BTreeSet<_> = adults.iter().copied().collect();
+        let now = SystemTime::now();
+
+        let mut liveness = AdultLiveness::load(root.path(), Some(encryption.clone()))?;
+        for _ in 0..11 {
+            let msg_id = MessageId::new();
+            assert!(liveness.new_read(msg_id, address, origin, targets.clone(), vec![]));
+            assert!(!liveness.new_read(msg_id, address, origin, targets.clone(), vec![]));
+            assert_eq!(
+                liveness.record_adult_read_liveness(msg_id, &adults[1], true),
+                ReadProgress::Respond(address, origin)
+            );
+            let _ = liveness.record_adult_read_liveness(msg_id, &adults[2], true);
+            // Reads not yet past their deadline are not penalised.
+            liveness.expire_reads(Instant::now(), now);
+            assert!(liveness.find_unresponsive_adults(now).is_empty());
+            liveness.expire_reads(Instant::now() + READ_DEADLINE, now);
+        }
+        let unresponsive = liveness.find_unresponsive_adults(now);
+        assert_eq!(unresponsive.len(), 1);
+        assert_eq!(unresponsive[0].0, adults[0]);
+        liveness.write()?;
+        drop(liveness);
+
+        // Resumed after a restart, with the penalty decaying over time.
+        let mut liveness = AdultLiveness::load(root.path(), Some(encryption))?;
+        assert_eq!(liveness.find_unresponsive_adults(now).len(), 1);
+        assert!(liveness.adults[&adults[1]].latency.is_some());
+        assert!(liveness
+            .find_unresponsive_adults(now + PENALTY_HALF_LIFE)
+            .is_empty());
+
+        // A departed Adult is no longer tracked.
+        liveness.retain_members_only(adults[1..].iter().copied().collect());
+        assert!(liveness.find_unresponsive_adults(now).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn retries_unacked_writes_to_alternate_adults() -> Result<()> {
+        let root =
+            TempDir::new("liveness").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let data = Blob::Public(PublicBlob::new(b"chunk".to_vec()));
+        let client_signed = ClientSigned {
+            public_key: PublicKey::Bls(SecretKey::random().public_key()),
+            signature: Signature::Bls(SecretKey::random().sign(b"chunk")),
+        };
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let holders: Vec<_> = (0..3).map(|_| XorName::random()).collect();
+        let msg_id = MessageId::new();
+        let now = SystemTime::now();
+
+        let mut liveness = AdultLiveness::load(root.path(), None)?;
+        assert!(liveness.new_write(
+            msg_id,
+            data,
+            client_signed.clone(),
+            origin,
+            holders.iter().copied().collect()
+        ));
+        assert!(!liveness.new_write(
+            msg_id,
+            Blob::Public(PublicBlob::new(b"chunk".to_vec())),
+            client_signed,
+            origin,
+            holders.iter().copied().collect()
+        ));
+        liveness.record_adult_write_liveness(msg_id, &holders[0], true);
+        liveness.record_adult_write_liveness(msg_id, &holders[1], false);
+        assert!(liveness.expire_writes_at(Instant::now(), now).is_empty());
+
+        // The holder that failed and the silent one are replaced, and only the latter penalised.
+        let mut retries = liveness.expire_writes_at(Instant::now() + WRITE_DEADLINE, now);
+        assert_eq!(retries.len(), 1);
+        let mut retry = retries.remove(0);
+        assert_eq!(retry.count, 2);
+        assert_eq!(retry.tried, holders.iter().copied().collect());
+        assert_eq!(liveness.adults[&holders[1]].penalty, 0.0);
+        assert_eq!(liveness.adults[&holders[2]].penalty, 1.0);
+
+        // Retried until acked by the alternate Adults.
+        let alternates: BTreeSet<_> = (0..retry.count).map(|_| XorName::random()).collect();
+        liveness.retry_write(retry.clone(), alternates.clone());
+        for alternate in &alternates {
+            liveness.record_adult_write_liveness(msg_id, alternate, true);
+        }
+        assert!(liveness.writes.is_empty());
+
+        // Or given up on after the last attempt.
+        for _ in 1..MAX_WRITE_ATTEMPTS - 1 {
+            liveness.retry_write(retry, alternates.clone());
+            let mut retries = liveness.expire_writes_at(Instant::now() + WRITE_DEADLINE, now);
+            assert_eq!(retries.len(), 1);
+            retry = retries.remove(0);
+        }
+        liveness.retry_write(retry, alternates);
+        assert!(liveness
+            .expire_writes_at(Instant::now() + WRITE_DEADLINE, now)
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn hedges_reads_to_next_holders() -> Result<()> {
+        let root =
+            TempDir::new("liveness").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let address = *Blob::Public(PublicBlob::new(b"chunk".to_vec())).address();
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let holders: BTreeSet<_> = (0..4).map(|_| XorName::random()).collect();
+        let by_distance: Vec<_> = holders
+            .iter()
+            .copied()
+            .sorted_by(|lhs, rhs| address.name().cmp_distance(lhs, rhs))
+            .collect();
+
+        let mut liveness = AdultLiveness::load(root.path(), None)?;
+        assert_eq!(
+            liveness.holders_by_preference(address.name(), &holders),
+            by_distance
+        );
+        assert_eq!(liveness.hedge_delay(&by_distance[0]), MAX_HEDGE_DELAY);
+
+        // The fastest holder is queried first.
+        liveness.track(&holders);
+        for (holder, latency) in by_distance.iter().zip(&[0.5, 0.3, 0.1, 0.2]) {
+            if let Some(record) = liveness.adults.get_mut(holder) {
+                record.latency = Some(*latency);
+            }
+        }
+        let ordered = liveness.holders_by_preference(address.name(), &holders);
+        assert_eq!(
+            ordered,
+            vec![
+                by_distance[2],
+                by_distance[0],
+                by_distance[1],
+                by_distance[3]
+            ]
+        );
+        assert!(liveness.hedge_delay(&ordered[0]) < MAX_HEDGE_DELAY);
+
+        // Slow to respond, the next holder is queried.
+        let msg_id = MessageId::new();
+        assert!(liveness.new_read(
+            msg_id,
+            address,
+            origin,
+            btree_set!(ordered[0]),
+            ordered[1..].to_vec()
+        ));
+        assert_eq!(
+            liveness.hedge_read(msg_id),
+            Some((address, origin, ordered[1]))
+        );
+
+        // Failing to respond, all the others are queried instead of forwarding the error.
+        assert_eq!(
+            liveness.record_adult_read_liveness(msg_id, &ordered[1], false),
+            ReadProgress::FallBack(address, origin, ordered[2..].iter().copied().collect())
+        );
+        assert_eq!(liveness.hedge_read(msg_id), None);
+
+        // A failure is not forwarded while other holders are yet to respond.
+        assert_eq!(
+            liveness.record_adult_read_liveness(msg_id, &ordered[3], false),
+            ReadProgress::Drop
+        );
+
+        // Only the first success is forwarded.
+        assert_eq!(
+            liveness.record_adult_read_liveness(msg_id, &ordered[2], true),
+            ReadProgress::Respond(address, origin)
+        );
+        assert_eq!(
+            liveness.record_adult_read_liveness(msg_id, &ordered[0], true),
+            ReadProgress::Drop
+        );
+        assert!(liveness.ops.is_empty());
+
+        // The error is forwarded once all the holders failed.
+        let msg_id = MessageId::new();
+        assert!(liveness.new_read(msg_id, address, origin, holders.clone(), vec![]));
+        for holder in &ordered[1..] {
+            assert_eq!(
+                liveness.record_adult_read_liveness(msg_id, holder, false),
+                ReadProgress::Drop
+            );
+        }
+        assert_eq!(
+            liveness.record_adult_read_liveness(msg_id, &ordered[0], false),
+            ReadProgress::Respond(address, origin)
+        );
+        assert!(liveness.ops.is_empty());
+        Ok(())
+    }
 }
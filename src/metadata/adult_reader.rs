@@ -29,18 +29,7 @@ impl AdultReader {
     }
 
     /// Dynamic state
-    pub async fn non_full_adults_closest_to(
-        &self,
-        name: &XorName,
-        full_adults: &BTreeSet<XorName>,
-        count: usize,
-    ) -> BTreeSet<XorName> {
-        self.network
-            .our_adults_sorted_by_distance_to(name)
-            .await
-            .into_iter()
-            .filter(|name| !full_adults.contains(name))
-            .take(count)
-            .collect::<BTreeSet<_>>()
+    pub async fn our_adults(&self) -> BTreeSet<XorName> {
+        self.network.our_adults().await
     }
 }
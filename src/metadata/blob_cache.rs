@@ -0,0 +1,146 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use sn_data_types::{Blob, BlobAddress};
+use std::collections::{BTreeMap, HashMap};
+
+/// Cache of the public chunks last read through us, for serving reads of popular ones without
+/// querying their holders.
+///
+/// The chunks least recently read are evicted once the cache holds more than its capacity in
+/// bytes. Private chunks are never cached, as their reads have to be checked by their holders.
+pub(super) struct BlobCache {
+    capacity: u64,
+    size: u64,
+    // The cached chunks, with when they were last read.
+    blobs: HashMap<BlobAddress, (Blob, u64)>,
+    // The addresses of the cached chunks by when they were last read.
+    recency: BTreeMap<u64, BlobAddress>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl BlobCache {
+    /// A cache of up to `capacity` bytes, disabled if 0.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            blobs: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns the chunk at `address` if cached, counting a hit or a miss.
+    pub fn get(&mut self, address: &BlobAddress) -> Option<Blob> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.tick += 1;
+        match self.blobs.get_mut(address) {
+            Some((blob, read)) => {
+                let _ = self.recency.remove(read);
+                *read = self.tick;
+                let _ = self.recency.insert(self.tick, *address);
+                self.hits += 1;
+                Some(blob.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches `blob` if public and not larger than the cache, evicting the chunks least recently
+    /// read to make room for it.
+    pub fn insert(&mut self, blob: Blob) {
+        let size = blob.value().len() as u64;
+        if blob.is_private() || size > self.capacity || self.blobs.contains_key(blob.address()) {
+            return;
+        }
+        while self.size + size > self.capacity {
+            let oldest = match self.recency.keys().next() {
+                Some(read) => *read,
+                None => break,
+            };
+            if let Some(address) = self.recency.remove(&oldest) {
+                self.remove(&address);
+            }
+        }
+        self.tick += 1;
+        self.size += size;
+        let _ = self.recency.insert(self.tick, *blob.address());
+        let _ = self.blobs.insert(*blob.address(), (blob, self.tick));
+    }
+
+    /// Removes the chunk at `address` from the cache, e.g. as it was deleted.
+    pub fn remove(&mut self, address: &BlobAddress) {
+        if let Some((blob, read)) = self.blobs.remove(address) {
+            let _ = self.recency.remove(&read);
+            self.size -= blob.value().len() as u64;
+        }
+    }
+
+    /// The number of reads served from the cache, and not.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlobCache;
+    use bls::SecretKey;
+    use sn_data_types::{Blob, PrivateBlob, PublicBlob, PublicKey};
+
+    #[test]
+    fn evicts_least_recently_read_public_chunks() {
+        let blobs: Vec<_> = (0..3)
+            .map(|index| Blob::Public(PublicBlob::new(vec![index; 10])))
+            .collect();
+        let mut cache = BlobCache::new(20);
+        cache.insert(blobs[0].clone());
+        cache.insert(blobs[1].clone());
+        assert_eq!(cache.get(blobs[0].address()), Some(blobs[0].clone()));
+
+        // The least recently read one makes room for the new one.
+        cache.insert(blobs[2].clone());
+        assert_eq!(cache.get(blobs[1].address()), None);
+        assert_eq!(cache.get(blobs[0].address()), Some(blobs[0].clone()));
+        assert_eq!(cache.get(blobs[2].address()), Some(blobs[2].clone()));
+        assert_eq!(cache.stats(), (3, 1));
+
+        cache.remove(blobs[2].address());
+        assert_eq!(cache.get(blobs[2].address()), None);
+        assert_eq!(cache.size, 10);
+
+        // Private and oversized chunks are never cached.
+        let owner = PublicKey::Bls(SecretKey::random().public_key());
+        let private = Blob::Private(PrivateBlob::new(vec![0; 10], owner));
+        cache.insert(private.clone());
+        assert_eq!(cache.get(private.address()), None);
+        let large = Blob::Public(PublicBlob::new(vec![0; 30]));
+        cache.insert(large.clone());
+        assert_eq!(cache.get(large.address()), None);
+
+        // A disabled cache counts nothing.
+        let mut cache = BlobCache::new(0);
+        cache.insert(blobs[0].clone());
+        assert_eq!(cache.get(blobs[0].address()), None);
+        assert_eq!(cache.stats(), (0, 0));
+    }
+}
@@ -9,11 +9,12 @@
 use crate::{
     btree_set,
     capacity::{Capacity, CHUNK_COPY_COUNT},
+    encryption::EncryptionKey,
     error::convert_to_error_message,
     node_ops::{NodeDuties, NodeDuty},
-    Error, Result,
+    ChunkReadStrategy, Error, Result,
 };
-use log::{info, warn};
+use log::{debug, info, warn};
 use sn_data_types::{Blob, BlobAddress, PublicKey};
 use sn_messaging::{
     client::{BlobDataExchange, BlobRead, BlobWrite, ClientSigned, CmdError, QueryResponse},
@@ -25,25 +26,38 @@ use sn_routing::Prefix;
 use std::{
     collections::BTreeSet,
     fmt::{self, Display, Formatter},
+    path::Path,
 };
 use xor_name::XorName;
 
 use super::{
-    adult_liveness::AdultLiveness, build_client_error_response, build_client_query_response,
+    adult_liveness::{AdultLiveness, ReadProgress},
+    blob_cache::BlobCache,
+    build_client_error_response, build_client_query_response,
 };
 
 /// Operations over the data type Blob.
 pub(super) struct BlobRecords {
     capacity: Capacity,
     adult_liveness: AdultLiveness,
+    read_strategy: ChunkReadStrategy,
+    cache: BlobCache,
 }
 
 impl BlobRecords {
-    pub(super) fn new(capacity: Capacity) -> Self {
-        Self {
+    pub(super) fn new(
+        capacity: Capacity,
+        root_dir: &Path,
+        encryption: Option<EncryptionKey>,
+        read_strategy: ChunkReadStrategy,
+        cache_size: u64,
+    ) -> Result<Self> {
+        Ok(Self {
             capacity,
-            adult_liveness: AdultLiveness::new(),
-        }
+            adult_liveness: AdultLiveness::load(root_dir, encryption)?,
+            read_strategy,
+            cache: BlobCache::new(cache_size),
+        })
     }
 
     pub async fn get_data_of(&self, prefix: Prefix) -> BlobDataExchange {
@@ -54,7 +68,7 @@ impl BlobRecords {
 
     pub async fn update(&self, blob_data: BlobDataExchange) {
         let BlobDataExchange { full_adults } = blob_data;
-        self.capacity.insert_full_adults(full_adults).await
+        let _ = self.capacity.insert_full_adults(full_adults).await;
     }
 
     /// Registered holders not present in provided list of members
@@ -83,29 +97,21 @@ impl BlobRecords {
         }
     }
 
-    /// Adds a given node to the list of full nodes.
-    pub async fn increase_full_node_count(&mut self, node_id: PublicKey) {
+    /// Adds a given node to the list of full nodes, or renews its lease there,
+    /// see `FULL_ADULT_LEASE`. Returns whether it was not counted as full before.
+    ///
+    /// It is no longer counted as full once it stops reporting it.
+    pub async fn increase_full_node_count(&mut self, node_id: PublicKey) -> bool {
         info!(
             "No. of full Adults: {:?}",
             self.capacity.full_adults_count().await
         );
         info!("Increasing full Adults count");
-        self.capacity
+        !self
+            .capacity
             .insert_full_adults(btree_set!(XorName::from(node_id)))
-            .await;
-    }
-
-    /// Removes a given node from the list of full nodes.
-    #[allow(unused)] // TODO: Remove node from full list at 50% ?
-    async fn decrease_full_adults_count_if_present(&mut self, node_name: XorName) {
-        info!(
-            "No. of Full Nodes: {:?}",
-            self.capacity.full_adults_count().await
-        );
-        info!("Checking if {:?} is present as full_node", node_name);
-        self.capacity
-            .remove_full_adults(btree_set!(node_name))
-            .await;
+            .await
+            .is_empty()
     }
 
     async fn send_chunks_to_adults(
@@ -129,10 +135,40 @@ impl BlobRecords {
                 .await;
         }
 
+        if !self.adult_liveness.new_write(
+            msg_id,
+            data.clone(),
+            client_signed.clone(),
+            origin,
+            target_holders.clone(),
+        ) {
+            info!(
+                "Operation with MessageId {:?} is already in progress",
+                msg_id
+            );
+            return Ok(NodeDuty::NoOp);
+        }
+
+        Ok(Self::chunk_write_to(
+            target_holders,
+            data,
+            msg_id,
+            client_signed,
+            origin,
+        ))
+    }
+
+    fn chunk_write_to(
+        targets: BTreeSet<XorName>,
+        data: Blob,
+        msg_id: MessageId,
+        client_signed: ClientSigned,
+        origin: EndUser,
+    ) -> NodeDuty {
         let blob_write = BlobWrite::New(data);
 
-        Ok(NodeDuty::SendToNodes {
-            targets: target_holders,
+        NodeDuty::SendToNodes {
+            targets,
             msg: NodeMsg::NodeCmd {
                 cmd: NodeCmd::Chunks {
                     cmd: blob_write,
@@ -142,7 +178,7 @@ impl BlobRecords {
                 id: msg_id,
             },
             aggregation: Aggregation::AtDestination,
-        })
+        }
     }
 
     async fn store(
@@ -173,28 +209,97 @@ impl BlobRecords {
             )));
         }
         let mut duties = vec![];
-        if let Some((_address, end_user)) = self.adult_liveness.record_adult_read_liveness(
+        match self.adult_liveness.record_adult_read_liveness(
             correlation_id,
             &src,
             response.is_success(),
         ) {
-            // If a full adult responds with error. Drop the response
-            if !response.is_success() && self.capacity.is_full(&src).await {
-                // We've already responded already with a success
-                // so do nothing
-            } else {
-                duties.push(NodeDuty::Send(build_client_query_response(
-                    response,
+            ReadProgress::Respond(address, end_user) => {
+                if let QueryResponse::GetBlob(Ok(blob)) = &response {
+                    if blob.address() == &address {
+                        self.cache.insert(blob.clone());
+                    }
+                }
+                // If a full adult responds with error. Drop the response
+                if !response.is_success() && self.capacity.is_full(&src).await {
+                    // We've already responded already with a success
+                    // so do nothing
+                } else {
+                    duties.push(NodeDuty::Send(build_client_query_response(
+                        response,
+                        correlation_id,
+                        end_user,
+                    )));
+                }
+            }
+            ReadProgress::FallBack(address, end_user, holders) => {
+                info!(
+                    "Querying all other holders of chunk {:?} as {} failed to respond with it",
+                    address, src
+                );
+                duties.push(Self::chunk_query_to(
+                    holders,
+                    address,
                     correlation_id,
                     end_user,
-                )));
+                ));
+            }
+            ReadProgress::Drop => (),
+        }
+        Ok(duties)
+    }
+
+    /// Records the ack of an Adult for storing a chunk.
+    pub fn record_adult_write_liveness(
+        &mut self,
+        correlation_id: MessageId,
+        result: Result<(), CmdError>,
+        src: XorName,
+    ) {
+        if let Err(error) = &result {
+            warn!("Adult {} failed to store a chunk: {:?}", src, error);
+        }
+        self.adult_liveness
+            .record_adult_write_liveness(correlation_id, &src, result.is_ok());
+    }
+
+    /// Penalises the Adults for the reads and writes they did not respond to in time, retries
+    /// the writes to alternate Adults, and proposes those unresponsive of late as offline.
+    pub async fn check_adult_liveness(&mut self) -> Result<NodeDuties> {
+        let mut duties = vec![];
+        for retry in self.adult_liveness.expire_writes() {
+            let targets = self
+                .capacity
+                .get_alternate_holder_adults(retry.data.name(), &retry.tried, retry.count)
+                .await;
+            if targets.is_empty() {
+                warn!(
+                    "No alternate Adults to store {} more copies of chunk {:?}",
+                    retry.count,
+                    retry.data.address()
+                );
+                continue;
             }
+            info!(
+                "Retrying to store chunk {:?} at {:?}",
+                retry.data.address(),
+                targets
+            );
+            duties.push(Self::chunk_write_to(
+                targets.clone(),
+                retry.data.clone(),
+                retry.msg_id,
+                retry.client_signed.clone(),
+                retry.origin,
+            ));
+            self.adult_liveness.retry_write(retry, targets);
         }
+
         let mut unresponsive_adults = Vec::new();
-        for (name, count) in self.adult_liveness.find_unresponsive_adults() {
+        for (name, penalty) in self.adult_liveness.check()? {
             warn!(
-                "Adult {} has {} pending ops. It might be unresponsive",
-                name, count
+                "Adult {} has a penalty of {:.1} for reads timed out. It might be unresponsive",
+                name, penalty
             );
             unresponsive_adults.push(name);
         }
@@ -225,6 +330,10 @@ impl BlobRecords {
         client_signed: ClientSigned,
         origin: EndUser,
     ) -> Result<NodeDuty> {
+        self.cache.remove(&address);
+        // TODO: copies left on Adults that were holders before churn are never deleted. It needs
+        // tombstones of the deleted addresses, synced in `DataExchange` and pushed to the Adults,
+        // but sn_messaging has neither room nor a message for them yet.
         let targets = self.capacity.get_chunk_holder_adults(address.name()).await;
 
         let msg = NodeMsg::NodeCmd {
@@ -270,7 +379,7 @@ impl BlobRecords {
         read: &BlobRead,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         match read {
             BlobRead::Get(address) => self.get(*address, msg_id, origin).await,
         }
@@ -281,42 +390,112 @@ impl BlobRecords {
         address: BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let targets = self.capacity.get_chunk_holder_adults(address.name()).await;
+    ) -> Result<NodeDuties> {
+        if address.is_public() && self.cache.is_enabled() {
+            let cached = self.cache.get(&address);
+            let (hits, misses) = self.cache.stats();
+            debug!(
+                "Chunk cache {} for {:?} (hits: {}, misses: {})",
+                if cached.is_some() { "hit" } else { "miss" },
+                address,
+                hits,
+                misses
+            );
+            if let Some(blob) = cached {
+                return Ok(vec![NodeDuty::Send(build_client_query_response(
+                    QueryResponse::GetBlob(Ok(blob)),
+                    msg_id,
+                    origin,
+                ))]);
+            }
+        }
 
-        if targets.is_empty() {
-            return self
-                .send_error(
+        let holders = self.capacity.get_chunk_holder_adults(address.name()).await;
+
+        if holders.is_empty() {
+            return Ok(vec![
+                self.send_error(
                     Error::NoAdults(self.capacity.our_prefix().await),
                     msg_id,
                     origin,
                 )
-                .await;
+                .await?,
+            ]);
         }
 
-        if self
-            .adult_liveness
-            .new_read(msg_id, address, origin, targets.clone())
-        {
-            let msg = NodeMsg::NodeQuery {
-                query: NodeQuery::Chunks {
-                    query: BlobRead::Get(address),
-                    origin,
-                },
-                id: msg_id,
-            };
+        let (targets, remaining) = match self.read_strategy {
+            ChunkReadStrategy::All => (holders, vec![]),
+            ChunkReadStrategy::Hedged => {
+                let mut ordered = self
+                    .adult_liveness
+                    .holders_by_preference(address.name(), &holders);
+                let remaining = ordered.split_off(1);
+                (ordered.into_iter().collect(), remaining)
+            }
+        };
 
-            Ok(NodeDuty::SendToNodes {
-                msg,
-                targets,
-                aggregation: Aggregation::None,
-            })
-        } else {
+        if !self.adult_liveness.new_read(
+            msg_id,
+            address,
+            origin,
+            targets.clone(),
+            remaining.clone(),
+        ) {
             info!(
                 "Operation with MessageId {:?} is already in progress",
                 msg_id
             );
-            Ok(NodeDuty::NoOp)
+            return Ok(vec![]);
+        }
+
+        let mut duties = vec![];
+        if let (Some(first), false) = (targets.iter().next(), remaining.is_empty()) {
+            duties.push(NodeDuty::HedgeChunkRead {
+                msg_id,
+                delay: self.adult_liveness.hedge_delay(first),
+            });
+        }
+        duties.insert(0, Self::chunk_query_to(targets, address, msg_id, origin));
+        Ok(duties)
+    }
+
+    /// Queries the next holder for the read `msg_id`, if it has yet to get a response.
+    pub async fn hedge_read(&mut self, msg_id: MessageId) -> Result<NodeDuties> {
+        let (address, origin, holder) = match self.adult_liveness.hedge_read(msg_id) {
+            Some(next) => next,
+            None => return Ok(vec![]),
+        };
+        info!(
+            "Querying {} for chunk {:?} as the holders queried are slow to respond",
+            holder, address
+        );
+        Ok(vec![
+            Self::chunk_query_to(btree_set!(holder), address, msg_id, origin),
+            NodeDuty::HedgeChunkRead {
+                msg_id,
+                delay: self.adult_liveness.hedge_delay(&holder),
+            },
+        ])
+    }
+
+    fn chunk_query_to(
+        targets: BTreeSet<XorName>,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> NodeDuty {
+        let msg = NodeMsg::NodeQuery {
+            query: NodeQuery::Chunks {
+                query: BlobRead::Get(address),
+                origin,
+            },
+            id: msg_id,
+        };
+
+        NodeDuty::SendToNodes {
+            msg,
+            targets,
+            aggregation: Aggregation::None,
         }
     }
 }
@@ -10,7 +10,10 @@ use super::{
     blob_records::BlobRecords, map_storage::MapStorage, register_storage::RegisterStorage,
     sequence_storage::SequenceStorage,
 };
-use crate::{node_ops::NodeDuty, Error, Result};
+use crate::{
+    node_ops::{NodeDuties, NodeDuty},
+    Error, Result,
+};
 use log::info;
 use sn_data_types::PublicKey;
 use sn_messaging::{
@@ -49,9 +52,9 @@ impl ElderStores {
         msg_id: MessageId,
         requester: PublicKey,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        match &query {
-            DataQuery::Blob(read) => self.blob_records.read(read, msg_id, origin).await,
+    ) -> Result<NodeDuties> {
+        let duty = match &query {
+            DataQuery::Blob(read) => return self.blob_records.read(read, msg_id, origin).await,
             DataQuery::Map(read) => self.map_storage.read(read, msg_id, requester, origin).await,
             DataQuery::Sequence(read) => {
                 self.sequence_storage
@@ -63,7 +66,8 @@ impl ElderStores {
                     .read(read, msg_id, requester, origin)
                     .await
             }
-        }
+        }?;
+        Ok(vec![duty])
     }
 
     pub async fn write(
@@ -102,6 +106,10 @@ impl ElderStores {
         }
     }
 
+    pub fn blob_records(&self) -> &BlobRecords {
+        &self.blob_records
+    }
+
     pub fn blob_records_mut(&mut self) -> &mut BlobRecords {
         &mut self.blob_records
     }
@@ -8,7 +8,11 @@
 
 use super::{build_client_error_response, build_client_query_response};
 use crate::{
-    chunk_store::MapChunkStore, error::convert_to_error_message, node_ops::NodeDuty, Error, Result,
+    chunk_store::{ChunkStore, FileBackend, MapChunkStore, StorageBackend},
+    encryption::EncryptionKey,
+    error::convert_to_error_message,
+    node_ops::NodeDuty,
+    Error, Result,
 };
 use log::{debug, info};
 use sn_data_types::{
@@ -26,16 +30,23 @@ use std::{
 };
 
 /// Operations over the data type Map.
-pub(super) struct MapStorage {
-    chunks: MapChunkStore,
+pub(super) struct MapStorage<B: StorageBackend = FileBackend> {
+    chunks: ChunkStore<Map, B>,
 }
 
 impl MapStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = MapChunkStore::new(path, max_capacity).await?;
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let chunks = MapChunkStore::new(path, max_capacity, compress, encryption).await?;
         Ok(Self { chunks })
     }
+}
 
+impl<B: StorageBackend> MapStorage<B> {
     pub(super) fn get_data_of(&self, prefix: Prefix) -> MapDataExchange {
         let store = &self.chunks;
         let data = self
@@ -478,8 +489,70 @@ impl MapStorage {
     }
 }
 
-impl Display for MapStorage {
+impl<B: StorageBackend> Display for MapStorage<B> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "MapStorage")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::MapStorage;
+    use crate::{
+        chunk_store::{ChunkStore, MemoryBackend},
+        node_ops::NodeDuty,
+        Result,
+    };
+    use bls::SecretKey;
+    use sn_data_types::{Map, PublicKey, SeqMap};
+    use sn_messaging::{client::MapWrite, EndUser, MessageId};
+    use sn_routing::{Prefix, XorName};
+
+    async fn in_memory() -> Result<MapStorage<MemoryBackend>> {
+        Ok(MapStorage {
+            chunks: ChunkStore::with_backend(MemoryBackend::new(), u64::MAX, false, None).await?,
+        })
+    }
+
+    #[tokio::test]
+    async fn stores_exchanges_and_deletes_maps_in_memory() -> Result<()> {
+        let mut storage = in_memory().await?;
+        let owner = PublicKey::Bls(SecretKey::random().public_key());
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let map = Map::from(SeqMap::new(XorName::random(), 15000, owner));
+        let address = *map.address();
+
+        let duty = storage
+            .write(MapWrite::New(map.clone()), MessageId::new(), owner, origin)
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert_eq!(storage.chunks.get(&address)?, map);
+
+        // The map is handed over to other Elders.
+        let mut other = in_memory().await?;
+        other.update(storage.get_data_of(Prefix::default())).await?;
+        assert_eq!(other.chunks.get(&address)?, map);
+
+        // Only its owner can delete it.
+        let stranger = PublicKey::Bls(SecretKey::random().public_key());
+        let duty = storage
+            .write(
+                MapWrite::Delete(address),
+                MessageId::new(),
+                stranger,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::Send(_)));
+        assert!(storage.chunks.has(&address));
+        let duty = storage
+            .write(MapWrite::Delete(address), MessageId::new(), owner, origin)
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert!(!storage.chunks.has(&address));
+        Ok(())
+    }
+}
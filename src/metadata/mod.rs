@@ -8,6 +8,7 @@
 
 mod adult_liveness;
 pub mod adult_reader;
+mod blob_cache;
 mod blob_records;
 mod elder_stores;
 mod map_storage;
@@ -16,9 +17,12 @@ mod sequence_storage;
 
 use crate::{
     capacity::Capacity,
+    encryption::EncryptionKey,
     node_ops::{MsgType, NodeDuties, NodeDuty, OutgoingMsg},
-    Result,
+    ChunkReadStrategy, Result,
 };
+pub use adult_liveness::ADULT_LIVENESS_CHECK_INTERVAL;
+pub(crate) use adult_liveness::LIVENESS_FILENAME;
 use blob_records::BlobRecords;
 use elder_stores::ElderStores;
 use map_storage::MapStorage;
@@ -51,11 +55,27 @@ pub struct Metadata {
 }
 
 impl Metadata {
-    pub async fn new(path: &Path, max_capacity: u64, capacity: Capacity) -> Result<Self> {
-        let blob_records = BlobRecords::new(capacity);
-        let map_storage = MapStorage::new(path, max_capacity).await?;
-        let sequence_storage = SequenceStorage::new(path, max_capacity).await?;
-        let register_storage = RegisterStorage::new(path, max_capacity).await?;
+    pub async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+        capacity: Capacity,
+        read_strategy: ChunkReadStrategy,
+        cache_size: u64,
+    ) -> Result<Self> {
+        let blob_records = BlobRecords::new(
+            capacity,
+            path,
+            encryption.clone(),
+            read_strategy,
+            cache_size,
+        )?;
+        let map_storage = MapStorage::new(path, max_capacity, compress, encryption.clone()).await?;
+        let sequence_storage =
+            SequenceStorage::new(path, max_capacity, compress, encryption.clone()).await?;
+        let register_storage =
+            RegisterStorage::new(path, max_capacity, compress, encryption).await?;
         let elder_stores = ElderStores::new(
             blob_records,
             map_storage,
@@ -71,10 +91,18 @@ impl Metadata {
         id: MessageId,
         requester: PublicKey,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         self.elder_stores.read(query, id, requester, origin).await
     }
 
+    /// Queries the next holder for a chunk read, unless it completed.
+    pub async fn hedge_chunk_read(&mut self, msg_id: MessageId) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_records_mut()
+            .hedge_read(msg_id)
+            .await
+    }
+
     pub async fn record_adult_read_liveness(
         &mut self,
         correlation_id: MessageId,
@@ -87,6 +115,24 @@ impl Metadata {
             .await
     }
 
+    pub fn record_adult_write_liveness(
+        &mut self,
+        correlation_id: MessageId,
+        result: Result<(), CmdError>,
+        src: XorName,
+    ) {
+        self.elder_stores
+            .blob_records_mut()
+            .record_adult_write_liveness(correlation_id, result, src)
+    }
+
+    pub async fn check_adult_liveness(&mut self) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_records_mut()
+            .check_adult_liveness()
+            .await
+    }
+
     pub async fn retain_members_only(&mut self, members: BTreeSet<XorName>) -> Result<()> {
         self.elder_stores
             .blob_records_mut()
@@ -107,8 +153,9 @@ impl Metadata {
             .await
     }
 
-    /// Adds a given node to the list of full nodes.
-    pub async fn increase_full_node_count(&mut self, node_id: PublicKey) {
+    /// Adds a given node to the list of full nodes,
+    /// returning whether it was not counted as full before.
+    pub async fn increase_full_node_count(&mut self, node_id: PublicKey) -> bool {
         self.elder_stores
             .blob_records_mut()
             .increase_full_node_count(node_id)
@@ -123,6 +170,15 @@ impl Metadata {
             .await
     }
 
+    /// Returns the Adults of our section known to be full.
+    pub async fn full_adults(&self) -> BTreeSet<XorName> {
+        self.elder_stores
+            .blob_records()
+            .get_data_of(Prefix::default())
+            .await
+            .full_adults
+    }
+
     pub async fn get_data_exchange_packet(&self, prefix: Prefix) -> Result<DataExchange> {
         self.elder_stores.get_data_of(prefix).await
     }
@@ -8,8 +8,11 @@
 
 use super::{build_client_error_response, build_client_query_response};
 use crate::{
-    chunk_store::RegisterChunkStore, error::convert_to_error_message, node_ops::NodeDuty, Error,
-    Result,
+    chunk_store::{ChunkStore, FileBackend, RegisterChunkStore, StorageBackend},
+    encryption::EncryptionKey,
+    error::convert_to_error_message,
+    node_ops::NodeDuty,
+    Error, Result,
 };
 use log::info;
 use sn_data_types::{
@@ -26,17 +29,24 @@ use std::{
 };
 
 /// Operations over the data type Register.
-pub(super) struct RegisterStorage {
-    chunks: RegisterChunkStore,
+pub(super) struct RegisterStorage<B: StorageBackend = FileBackend> {
+    chunks: ChunkStore<Register, B>,
 }
 
 impl RegisterStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = RegisterChunkStore::new(path, max_capacity).await?;
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let chunks = RegisterChunkStore::new(path, max_capacity, compress, encryption).await?;
 
         Ok(Self { chunks })
     }
+}
 
+impl<B: StorageBackend> RegisterStorage<B> {
     pub(super) async fn read(
         &self,
         read: &RegisterRead,
@@ -309,8 +319,82 @@ impl RegisterStorage {
     }
 }
 
-impl Display for RegisterStorage {
+impl<B: StorageBackend> Display for RegisterStorage<B> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "RegisterStorage")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::RegisterStorage;
+    use crate::{
+        chunk_store::{ChunkStore, MemoryBackend},
+        node_ops::NodeDuty,
+        Result,
+    };
+    use bls::SecretKey;
+    use sn_data_types::{register::Register, PublicKey};
+    use sn_messaging::{client::RegisterWrite, EndUser, MessageId};
+    use sn_routing::XorName;
+
+    #[tokio::test]
+    async fn stores_and_deletes_registers_in_memory() -> Result<()> {
+        let mut storage = RegisterStorage {
+            chunks: ChunkStore::with_backend(MemoryBackend::new(), u64::MAX, false, None).await?,
+        };
+        let owner = PublicKey::Bls(SecretKey::random().public_key());
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let register = Register::new_private(owner, XorName::random(), 15000, None);
+        let address = *register.address();
+
+        let duty = storage
+            .write(
+                RegisterWrite::New(register.clone()),
+                MessageId::new(),
+                owner,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert_eq!(storage.chunks.get(&address)?, register);
+
+        // It can't be stored twice.
+        let duty = storage
+            .write(
+                RegisterWrite::New(register.clone()),
+                MessageId::new(),
+                owner,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::Send(_)));
+
+        // Only its owner can delete it.
+        let stranger = PublicKey::Bls(SecretKey::random().public_key());
+        let duty = storage
+            .write(
+                RegisterWrite::Delete(address),
+                MessageId::new(),
+                stranger,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::Send(_)));
+        assert!(storage.chunks.has(&address));
+        let duty = storage
+            .write(
+                RegisterWrite::Delete(address),
+                MessageId::new(),
+                owner,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert!(!storage.chunks.has(&address));
+        Ok(())
+    }
+}
@@ -8,8 +8,11 @@
 
 use super::{build_client_error_response, build_client_query_response};
 use crate::{
-    chunk_store::SequenceChunkStore, error::convert_to_error_message, node_ops::NodeDuty, Error,
-    Result,
+    chunk_store::{ChunkStore, FileBackend, SequenceChunkStore, StorageBackend},
+    encryption::EncryptionKey,
+    error::convert_to_error_message,
+    node_ops::NodeDuty,
+    Error, Result,
 };
 use log::{debug, info};
 use sn_data_types::{
@@ -27,16 +30,23 @@ use std::{
 };
 
 /// Operations over the data type Sequence.
-pub(super) struct SequenceStorage {
-    chunks: SequenceChunkStore,
+pub(super) struct SequenceStorage<B: StorageBackend = FileBackend> {
+    chunks: ChunkStore<Sequence, B>,
 }
 
 impl SequenceStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = SequenceChunkStore::new(path, max_capacity).await?;
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        compress: bool,
+        encryption: Option<EncryptionKey>,
+    ) -> Result<Self> {
+        let chunks = SequenceChunkStore::new(path, max_capacity, compress, encryption).await?;
         Ok(Self { chunks })
     }
+}
 
+impl<B: StorageBackend> SequenceStorage<B> {
     pub fn get_data_of(&self, prefix: Prefix) -> SequenceDataExchange {
         let store = &self.chunks;
         let data = self
@@ -389,8 +399,81 @@ impl SequenceStorage {
     }
 }
 
-impl Display for SequenceStorage {
+impl<B: StorageBackend> Display for SequenceStorage<B> {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "SequenceStorage")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::SequenceStorage;
+    use crate::{
+        chunk_store::{ChunkStore, MemoryBackend},
+        node_ops::NodeDuty,
+        Result,
+    };
+    use bls::SecretKey;
+    use sn_data_types::{PublicKey, Sequence};
+    use sn_messaging::{client::SequenceWrite, EndUser, MessageId};
+    use sn_routing::{Prefix, XorName};
+
+    async fn in_memory() -> Result<SequenceStorage<MemoryBackend>> {
+        Ok(SequenceStorage {
+            chunks: ChunkStore::with_backend(MemoryBackend::new(), u64::MAX, false, None).await?,
+        })
+    }
+
+    #[tokio::test]
+    async fn stores_exchanges_and_deletes_sequences_in_memory() -> Result<()> {
+        let mut storage = in_memory().await?;
+        let owner = PublicKey::Bls(SecretKey::random().public_key());
+        let origin = EndUser {
+            xorname: XorName::random(),
+            socket_id: XorName::random(),
+        };
+        let sequence =
+            Sequence::new_private(owner, owner.to_string(), XorName::random(), 15000, None);
+        let address = *sequence.address();
+
+        let duty = storage
+            .write(
+                SequenceWrite::New(sequence.clone()),
+                MessageId::new(),
+                owner,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert_eq!(storage.chunks.get(&address)?, sequence);
+
+        // The sequence is handed over to other Elders.
+        let mut other = in_memory().await?;
+        other.update(storage.get_data_of(Prefix::default())).await?;
+        assert_eq!(other.chunks.get(&address)?, sequence);
+
+        // Only its owner can delete it.
+        let stranger = PublicKey::Bls(SecretKey::random().public_key());
+        let duty = storage
+            .write(
+                SequenceWrite::Delete(address),
+                MessageId::new(),
+                stranger,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::Send(_)));
+        assert!(storage.chunks.has(&address));
+        let duty = storage
+            .write(
+                SequenceWrite::Delete(address),
+                MessageId::new(),
+                owner,
+                origin,
+            )
+            .await?;
+        assert!(matches!(duty, NodeDuty::NoOp));
+        assert!(!storage.chunks.has(&address));
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Versioning of the on-disk layout of the root dir, and the migrations between versions.
+//!
+//! The version the root dir is at is kept in its `VERSION` file. A root dir without one predates
+//! versioning and is at version 0. On start, every step migrating to a version above the root
+//! dir's is run in order, and the `VERSION` file is updated after each one. Steps must be
+//! idempotent: a step interrupted by a crash is run again on the next start, and the root dirs of
+//! version 0 may have been partly migrated by the lazy migrations stores used to run on opening.
+//!
+//! Encryption can be enabled at any version, so encrypting the data stored before it was is not
+//! a versioned step. It is run after the versioned steps, on every start until it has completed,
+//! after which the key refuses plaintext.
+
+use crate::{
+    chunk_store::{BlobChunkStore, MapChunkStore, RegisterChunkStore, SequenceChunkStore},
+    chunks::HANDOFFS_FILENAME,
+    encryption::{self, EncryptionKey},
+    metadata::LIVENESS_FILENAME,
+    node::ELDER_STATE_FILENAME,
+    state_db::{NETWORK_KEYPAIR_FILENAME, REWARD_SECRET_KEY_FILENAME},
+    transfers::store,
+    utils, Error, Result,
+};
+use log::info;
+use sn_data_types::ReplicaEvent;
+use std::{fs, path::Path};
+
+const VERSION_FILENAME: &str = "VERSION";
+
+/// Version of the on-disk layout written by this node.
+pub(crate) const LAYOUT_VERSION: u32 = 2;
+
+/// A step migrating the root dir from the version before `version` to `version`.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    run: fn(&Path, Option<&EncryptionKey>) -> Result<()>,
+}
+
+/// The registered steps, in order.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "shard chunk files into prefix subdirectories",
+        run: shard_chunk_stores,
+    },
+    Migration {
+        version: 2,
+        description: "move transfer events from PickleDb stores to event logs",
+        run: migrate_transfer_stores,
+    },
+];
+
+/// Files of the root dir holding state other than chunks and transfers, written whole and encrypted
+/// if there is a key.
+const STATE_FILENAMES: &[&str] = &[
+    NETWORK_KEYPAIR_FILENAME,
+    REWARD_SECRET_KEY_FILENAME,
+    ELDER_STATE_FILENAME,
+    HANDOFFS_FILENAME,
+    LIVENESS_FILENAME,
+];
+
+/// Migrates the root dir at `root_dir` to `LAYOUT_VERSION`, using the `encryption` key its stores
+/// are encrypted with, if any. Data stored before encryption was enabled is then encrypted, and
+/// the key set to refuse plaintext.
+///
+/// Returns `Error::Configuration` if the root dir was written by a newer node.
+pub(crate) fn run(root_dir: &Path, encryption: Option<&mut EncryptionKey>) -> Result<()> {
+    let version = read_version(root_dir)?;
+    if version > LAYOUT_VERSION {
+        return Err(Error::Configuration(format!(
+            "The root dir at {} is at version {}, but this node only supports up to version {}",
+            root_dir.display(),
+            version,
+            LAYOUT_VERSION
+        )));
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+    {
+        info!(
+            "Migrating {} to version {}: {}",
+            root_dir.display(),
+            migration.version,
+            migration.description
+        );
+        (migration.run)(root_dir, encryption.as_deref())?;
+        write_version(root_dir, migration.version)?;
+    }
+
+    if let Some(key) = encryption.filter(|key| key.accepts_plaintext()) {
+        info!(
+            "Encrypting any data stored unencrypted at {}",
+            root_dir.display()
+        );
+        encrypt_plaintext(root_dir, key)?;
+        key.refuse_plaintext(root_dir)?;
+    }
+    Ok(())
+}
+
+/// Returns the version the root dir at `root_dir` is at, 0 if it has no `VERSION` file.
+pub(crate) fn read_version(root_dir: &Path) -> Result<u32> {
+    let path = root_dir.join(VERSION_FILENAME);
+    if !path.is_file() {
+        return Ok(0);
+    }
+    let contents = fs::read_to_string(&path)?;
+    contents.trim().parse().map_err(|_| {
+        Error::Configuration(format!(
+            "Invalid version '{}' in {}",
+            contents.trim(),
+            path.display()
+        ))
+    })
+}
+
+fn write_version(root_dir: &Path, version: u32) -> Result<()> {
+    utils::write_atomically(
+        &root_dir.join(VERSION_FILENAME),
+        format!("{}\n", version).as_bytes(),
+    )
+}
+
+fn shard_chunk_stores(root_dir: &Path, _: Option<&EncryptionKey>) -> Result<()> {
+    BlobChunkStore::migrate_flat_layout(root_dir)?;
+    MapChunkStore::migrate_flat_layout(root_dir)?;
+    SequenceChunkStore::migrate_flat_layout(root_dir)?;
+    RegisterChunkStore::migrate_flat_layout(root_dir)
+}
+
+fn migrate_transfer_stores(root_dir: &Path, encryption: Option<&EncryptionKey>) -> Result<()> {
+    store::migrate_pickle_dbs::<ReplicaEvent>(root_dir, encryption)
+}
+
+fn encrypt_plaintext(root_dir: &Path, key: &EncryptionKey) -> Result<()> {
+    BlobChunkStore::encrypt_plaintext(root_dir, key)?;
+    MapChunkStore::encrypt_plaintext(root_dir, key)?;
+    SequenceChunkStore::encrypt_plaintext(root_dir, key)?;
+    RegisterChunkStore::encrypt_plaintext(root_dir, key)?;
+    store::encrypt_plaintext_logs(root_dir, key)?;
+    for filename in STATE_FILENAMES {
+        let _ = encryption::encrypt_file(&root_dir.join(filename), key)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_version, run, write_version, LAYOUT_VERSION, MIGRATIONS};
+    use crate::{
+        chunk_store::BlobChunkStore,
+        encryption::{self, EncryptionKey},
+        state_db::{get_network_keypair, store_network_keypair},
+        to_db_key::ToDbKey,
+        transfers::store::TransferStore,
+        utils, Error, Result,
+    };
+    use ed25519_dalek::Keypair;
+    use pickledb::{PickleDb, PickleDbDumpPolicy};
+    use rand::rngs::OsRng;
+    use sn_data_types::{Blob, PublicBlob};
+    use std::{fs, path::Path};
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[test]
+    fn steps_are_consecutive() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+        assert_eq!(
+            MIGRATIONS.last().map_or(0, |migration| migration.version),
+            LAYOUT_VERSION
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_unversioned_root_dir() -> Result<()> {
+        let root = temp_dir()?;
+        let mut encryption = EncryptionKey::random();
+        let blob = Blob::Public(PublicBlob::new(b"chunk".to_vec()));
+        let flat_chunk = write_flat_chunk(root.path(), &blob).await?;
+        let transfers_dir = root.path().join("transfers");
+        let name = XorName::random().to_db_key()?;
+        write_pickle_db(&transfers_dir.join(format!("{}.db", name)), &encryption)?;
+
+        run(root.path(), Some(&mut encryption))?;
+        assert_eq!(read_version(root.path())?, LAYOUT_VERSION);
+        assert!(!flat_chunk.exists());
+        let chunk_store =
+            BlobChunkStore::new(root.path(), u64::MAX, false, Some(encryption.clone())).await?;
+        assert_eq!(chunk_store.get(blob.address())?, blob);
+        assert!(!transfers_dir.join(format!("{}.db", name)).exists());
+        let log = fs::read(transfers_dir.join(format!("{}.log", name)))?;
+        assert!(!log.is_empty());
+
+        // Running again changes nothing.
+        run(root.path(), Some(&mut encryption))?;
+        assert_eq!(read_version(root.path())?, LAYOUT_VERSION);
+        assert_eq!(fs::read(transfers_dir.join(format!("{}.log", name)))?, log);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encrypts_plaintext_once_encryption_is_enabled() -> Result<()> {
+        let root = temp_dir()?;
+        let blob = Blob::Public(PublicBlob::new(b"chunk".to_vec()));
+        let mut chunk_store = BlobChunkStore::new(root.path(), u64::MAX, false, None).await?;
+        chunk_store.put(&blob).await?;
+        drop(chunk_store);
+        let id = XorName::random();
+        let mut transfers = TransferStore::<String>::new(id, root.path(), None)?;
+        transfers.try_insert("event".to_string())?;
+        drop(transfers);
+        let keypair = Keypair::generate(&mut OsRng);
+        store_network_keypair(root.path(), keypair.to_bytes(), None).await?;
+        run(root.path(), None)?;
+
+        let mut encryption = EncryptionKey::load(root.path(), Some(b"secret"))?
+            .ok_or_else(|| Error::Logic("No key".to_string()))?;
+        run(root.path(), Some(&mut encryption))?;
+        assert!(!encryption.accepts_plaintext());
+
+        // What was stored before is read back with a key refusing plaintext.
+        let chunk_store =
+            BlobChunkStore::new(root.path(), u64::MAX, false, Some(encryption.clone())).await?;
+        assert_eq!(chunk_store.get(blob.address())?, blob);
+        let transfers = TransferStore::<String>::new(id, root.path(), Some(encryption.clone()))?;
+        assert_eq!(transfers.get_all()?, vec!["event".to_string()]);
+        let stored_keypair = get_network_keypair(root.path(), Some(&encryption)).await?;
+        assert_eq!(
+            stored_keypair.map(|keypair| keypair.to_bytes().to_vec()),
+            Some(keypair.to_bytes().to_vec())
+        );
+
+        // Plaintext is refused from then on, including after a restart.
+        let reloaded = EncryptionKey::load(root.path(), Some(b"secret"))?;
+        assert!(matches!(reloaded, Some(key) if !key.accepts_plaintext()));
+        assert!(encryption::decrypt(Some(&encryption), b"plaintext".to_vec()).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_migration() -> Result<()> {
+        let root = temp_dir()?;
+        let blob = Blob::Public(PublicBlob::new(b"chunk".to_vec()));
+        let flat_chunk = write_flat_chunk(root.path(), &blob).await?;
+
+        // A crash before the version was written leaves step 1 to be run again.
+        write_version(root.path(), 0)?;
+        BlobChunkStore::migrate_flat_layout(root.path())?;
+        run(root.path(), None)?;
+        assert_eq!(read_version(root.path())?, LAYOUT_VERSION);
+        assert!(!flat_chunk.exists());
+        let chunk_store = BlobChunkStore::new(root.path(), u64::MAX, false, None).await?;
+        assert_eq!(chunk_store.get(blob.address())?, blob);
+        Ok(())
+    }
+
+    #[test]
+    fn versions_fresh_root_dir() -> Result<()> {
+        let root = temp_dir()?;
+        run(root.path(), None)?;
+        assert_eq!(read_version(root.path())?, LAYOUT_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_newer_version() -> Result<()> {
+        let root = temp_dir()?;
+        write_version(root.path(), LAYOUT_VERSION + 1)?;
+        assert!(matches!(
+            run(root.path(), None),
+            Err(Error::Configuration(_))
+        ));
+        fs::write(root.path().join("VERSION"), "two")?;
+        assert!(read_version(root.path()).is_err());
+        Ok(())
+    }
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("migrations").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    // Stores `blob` in the flat layout of version 0, returning the path of its chunk file.
+    async fn write_flat_chunk(root_dir: &Path, blob: &Blob) -> Result<std::path::PathBuf> {
+        let mut chunk_store = BlobChunkStore::new(root_dir, u64::MAX, false, None).await?;
+        chunk_store.put(blob).await?;
+        drop(chunk_store);
+
+        let store_dir = root_dir.join("chunks").join("immutable");
+        let file_name = hex::encode(utils::serialise(blob.address())?);
+        let flat_path = store_dir.join(&file_name);
+        let name = blob.address().name();
+        let sharded_path = store_dir
+            .join(format!("{:02x}", name.0[0]))
+            .join(format!("{:02x}", name.0[1]))
+            .join(&file_name);
+        fs::rename(sharded_path, &flat_path)?;
+        Ok(flat_path)
+    }
+
+    // Writes a PickleDb transfer store of version 0 holding one encrypted event.
+    fn write_pickle_db(db_path: &Path, encryption: &EncryptionKey) -> Result<()> {
+        if let Some(dir) = db_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut db = PickleDb::new_bin(db_path, PickleDbDumpPolicy::DumpUponRequest);
+        db.set(
+            "0",
+            &encryption::encrypt(Some(encryption), b"event".to_vec())?,
+        )?;
+        db.dump()?;
+        Ok(())
+    }
+}
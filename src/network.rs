@@ -7,6 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
+    encryption::EncryptionKey,
     state_db::{get_network_keypair, store_network_keypair},
     utils, Config as NodeConfig, Error, Result,
 };
@@ -39,8 +40,12 @@ pub struct Network {
 
 #[allow(missing_docs)]
 impl Network {
-    pub async fn new(root_dir: &Path, config: &NodeConfig) -> Result<(Self, EventStream)> {
-        let keypair = get_network_keypair(root_dir).await?;
+    pub async fn new(
+        root_dir: &Path,
+        config: &NodeConfig,
+        encryption: Option<&EncryptionKey>,
+    ) -> Result<(Self, EventStream)> {
+        let keypair = get_network_keypair(root_dir, encryption).await?;
 
         let routing_config = RoutingConfig {
             first: config.is_first(),
@@ -50,7 +55,7 @@ impl Network {
         let (routing, event_stream) = RoutingNode::new(routing_config).await?;
 
         // Network keypair may have to be changed due to naming criteria or network requirements.
-        store_network_keypair(root_dir, routing.keypair_as_bytes().await).await?;
+        store_network_keypair(root_dir, routing.keypair_as_bytes().await, encryption).await?;
 
         Ok((
             Self {
@@ -61,7 +66,6 @@ impl Network {
     }
 
     /// Sign with our node's ED25519 key
-    #[allow(unused)]
     pub async fn sign_as_node<T: Serialize>(&self, data: &T) -> Result<Signature> {
         let data = utils::serialise(data)?;
         let sig = self.routing.sign_as_node(&data).await;
@@ -319,13 +323,4 @@ impl Network {
             .map(|p2p_node| *p2p_node.name())
             .collect::<BTreeSet<_>>()
     }
-
-    pub async fn our_adults_sorted_by_distance_to(&self, name: &XorName) -> Vec<XorName> {
-        self.routing
-            .our_adults_sorted_by_distance_to(name)
-            .await
-            .into_iter()
-            .map(|p2p_node| *p2p_node.name())
-            .collect::<Vec<_>>()
-    }
 }
@@ -0,0 +1,147 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Elder state persisted under the root dir, for a restarted Elder to resume from rather than
+//! rebuilding it all from its peers.
+
+use super::role::ElderRole;
+use crate::{
+    encryption::{self, EncryptionKey},
+    network::Network,
+    section_funds::{reward_process::OurSection, reward_stage::RewardStage, SectionFunds},
+    utils, Result,
+};
+use bls::PublicKeySet;
+use secured_linked_list::SecuredLinkedList;
+use serde::{Deserialize, Serialize};
+use sn_data_types::{NodeAge, PublicKey};
+use sn_routing::XorName;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+pub(crate) const ELDER_STATE_FILENAME: &str = "elder_state";
+
+/// Interval between two writes of the Elder state to disk.
+pub const ELDER_STATE_WRITE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The state of an Elder that isn't already kept on disk by its stores.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ElderState {
+    /// Our section chain when the state was written.
+    pub section_chain: SecuredLinkedList,
+    /// The key set of our replicas.
+    pub peer_replicas: PublicKeySet,
+    /// Our index in the key set of our replicas.
+    pub key_index: usize,
+    /// The reward wallets of the nodes of our section.
+    pub node_wallets: BTreeMap<XorName, (NodeAge, PublicKey)>,
+    /// The Adults of our section known to be full.
+    pub full_adults: BTreeSet<XorName>,
+    /// Our section and the stage reached by the reward process, if we were churning.
+    pub reward_process: Option<(OurSection, RewardStage)>,
+}
+
+impl ElderState {
+    /// Returns the current state of the `elder`.
+    pub async fn of(elder: &ElderRole, network: &Network) -> Result<Self> {
+        let (node_wallets, reward_process) = match &*elder.section_funds.read().await {
+            SectionFunds::KeepingNodeWallets(wallets) => (wallets.node_wallets(), None),
+            SectionFunds::Churning { process, wallets } => (
+                wallets.node_wallets(),
+                Some((process.section().clone(), process.stage().clone())),
+            ),
+        };
+        Ok(Self {
+            section_chain: network.section_chain().await,
+            peer_replicas: network.our_public_key_set().await?,
+            key_index: network.our_index().await?,
+            node_wallets,
+            full_adults: elder.meta_data.read().await.full_adults().await,
+            reward_process,
+        })
+    }
+
+    /// Tests if the state was written while our replicas had a key of `section_chain`.
+    pub fn is_of_section(&self, section_chain: &SecuredLinkedList) -> bool {
+        section_chain.has_key(&self.peer_replicas.public_key())
+    }
+
+    /// Writes the state under `root_dir`, encrypted if an `encryption` key is given.
+    pub fn write(&self, root_dir: &Path, encryption: Option<&EncryptionKey>) -> Result<()> {
+        let contents = encryption::encrypt(encryption, utils::serialise(self)?.to_vec())?;
+        utils::write_atomically(&root_dir.join(ELDER_STATE_FILENAME), &contents)
+    }
+
+    /// Returns the state written under `root_dir`, if any.
+    pub fn read(root_dir: &Path, encryption: Option<&EncryptionKey>) -> Result<Option<Self>> {
+        let path = root_dir.join(ELDER_STATE_FILENAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = encryption::decrypt(encryption, fs::read(path)?)?;
+        Ok(Some(utils::deserialise(&contents)?))
+    }
+
+    /// Removes the state written under `root_dir`, if any, e.g. once we are no longer an Elder.
+    pub fn remove(root_dir: &Path) -> Result<()> {
+        let path = root_dir.join(ELDER_STATE_FILENAME);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ElderState;
+    use crate::{encryption::EncryptionKey, Error, Result};
+    use bls::{SecretKey, SecretKeySet};
+    use secured_linked_list::SecuredLinkedList;
+    use sn_data_types::PublicKey;
+    use sn_routing::XorName;
+    use tempdir::TempDir;
+
+    #[test]
+    fn write_read_and_remove() -> Result<()> {
+        let root =
+            TempDir::new("elder_state").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let encryption = EncryptionKey::random();
+        let key_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let node = XorName::random();
+        let wallet = PublicKey::from(SecretKey::random().public_key());
+
+        assert!(ElderState::read(root.path(), Some(&encryption))?.is_none());
+        let state = ElderState {
+            section_chain: SecuredLinkedList::new(key_set.public_keys().public_key()),
+            peer_replicas: key_set.public_keys(),
+            key_index: 0,
+            node_wallets: vec![(node, (5, wallet))].into_iter().collect(),
+            full_adults: vec![node].into_iter().collect(),
+            reward_process: None,
+        };
+        state.write(root.path(), Some(&encryption))?;
+        assert!(ElderState::read(root.path(), None).is_err());
+
+        let read = ElderState::read(root.path(), Some(&encryption))?
+            .ok_or_else(|| Error::Logic("Elder state was not read back".to_string()))?;
+        assert_eq!(read.node_wallets, state.node_wallets);
+        assert_eq!(read.full_adults, state.full_adults);
+        assert!(read.is_of_section(&state.section_chain));
+        let other_key = SecretKey::random().public_key();
+        assert!(!read.is_of_section(&SecuredLinkedList::new(other_key)));
+
+        ElderState::remove(root.path())?;
+        assert!(ElderState::read(root.path(), Some(&encryption))?.is_none());
+        Ok(())
+    }
+}
@@ -7,18 +7,24 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    elder_state::{ElderState, ELDER_STATE_WRITE_INTERVAL},
     interaction::push_state,
     messaging::{send, send_error, send_support, send_to_nodes},
     role::{AdultRole, Role},
 };
 use crate::{
-    chunks::Chunks,
+    capacity::AdultsStorageInfo,
+    chunks::{
+        Chunks, CHUNK_HANDOFF_RETRY_INTERVAL, CHUNK_REPUBLISH_FALLBACK_TIMEOUT,
+        CHUNK_SCRUB_INTERVAL, STORAGE_FULL_RENEW_INTERVAL,
+    },
     event_mapping::MsgContext,
+    metadata::ADULT_LIVENESS_CHECK_INTERVAL,
     node_ops::{NodeDuties, NodeDuty},
     section_funds::{reward_stage::RewardStage, Credits, SectionFunds},
     Error, Node, Result,
 };
-use log::{debug, info};
+use log::{debug, info, warn};
 use sn_messaging::{
     client::{Cmd, ProcessMsg},
     node::{NodeMsg, NodeQuery},
@@ -26,7 +32,7 @@ use sn_messaging::{
 };
 use sn_routing::ELDER_SIZE;
 use std::sync::Arc;
-use tokio::{sync::RwLock, task::JoinHandle};
+use tokio::{sync::RwLock, task::JoinHandle, time::sleep};
 use xor_name::XorName;
 
 const DATA_SECTION_TARGET_COUNT: usize = 3;
@@ -303,11 +309,21 @@ impl Node {
             }
             NodeDuty::LevelDown => {
                 info!("Getting Demoted");
+                if let Err(error) = ElderState::remove(self.node_info.path()) {
+                    warn!("Error removing stored Elder state: {:?}", error);
+                }
                 let capacity = self.used_space.max_capacity().await;
                 self.role = Role::Adult(AdultRole {
                     chunks: Arc::new(RwLock::new(
-                        Chunks::new(self.node_info.root_dir.as_path(), capacity).await?,
+                        Chunks::new(
+                            self.node_info.root_dir.as_path(),
+                            capacity,
+                            self.node_info.chunk_compression,
+                            self.node_info.encryption.clone(),
+                        )
+                        .await?,
                     )),
+                    storage_info: AdultsStorageInfo::new(),
                 });
                 Ok(NodeTask::None)
             }
@@ -450,8 +466,9 @@ impl Node {
             NodeDuty::ReadChunk { read, msg_id } => {
                 let adult = self.role.as_adult()?.clone();
                 let handle = tokio::spawn(async move {
-                    let mut ops = vec![adult.chunks.read().await.read(&read, msg_id)];
-                    ops.extend(adult.chunks.read().await.check_storage().await?);
+                    let chunks = adult.chunks.read().await;
+                    let mut ops = vec![chunks.read(&read, msg_id)];
+                    ops.extend(chunks.check_storage().await?);
                     Ok(NodeTask::from(ops))
                 });
                 Ok(NodeTask::Thread(handle))
@@ -478,6 +495,17 @@ impl Node {
             }
             NodeDuty::ProcessRepublish { chunk, msg_id, .. } => {
                 info!("Processing republish with MessageId: {:?}", msg_id);
+                if let Ok(adult) = self.role.as_adult() {
+                    // The good copy of a chunk we found corrupt.
+                    let adult = adult.clone();
+                    let handle = tokio::spawn(async move {
+                        if !adult.chunks.write().await.restore(chunk).await? {
+                            debug!("Dropping chunk not requested for restoring");
+                        }
+                        Ok(NodeTask::None)
+                    });
+                    return Ok(NodeTask::Thread(handle));
+                }
                 let elder = self.role.as_elder()?.clone();
                 let handle = tokio::spawn(async move {
                     Ok(NodeTask::from(vec![
@@ -486,6 +514,91 @@ impl Node {
                 });
                 Ok(NodeTask::Thread(handle))
             }
+            NodeDuty::GetChunkForRepublish {
+                address,
+                msg_id,
+                origin,
+            } => {
+                let adult = self.role.as_adult()?.clone();
+                let handle = tokio::spawn(async move {
+                    Ok(NodeTask::from(vec![adult
+                        .chunks
+                        .read()
+                        .await
+                        .get_for_republish(&address, msg_id, origin)]))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::ScrubChunks => {
+                // Only Adults hold chunks, but we keep scheduling the
+                // scrubbing as Elders too, in case we get demoted.
+                let adult = self.role.as_adult().ok().cloned();
+                let scrub_rate = self.node_info.chunk_scrub_rate;
+                let network_api = self.network_api.clone();
+                let handle = tokio::spawn(async move {
+                    sleep(CHUNK_SCRUB_INTERVAL).await;
+                    let mut ops = vec![];
+                    if let Some(adult) = adult {
+                        let corrupt = adult.chunks.write().await.scrub(scrub_rate).await;
+                        match corrupt {
+                            Ok(corrupt) if !corrupt.is_empty() => {
+                                let our_name = network_api.our_name().await;
+                                let adults = network_api.our_adults().await;
+                                for address in corrupt {
+                                    ops.extend(
+                                        adult.request_restore(address, &our_name, &adults).await,
+                                    );
+                                }
+                            }
+                            Ok(_) => (),
+                            Err(error) => warn!("Error scrubbing chunks: {:?}", error),
+                        }
+                    }
+                    ops.push(NodeDuty::ScrubChunks);
+                    Ok(NodeTask::from(ops))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::WriteElderState => {
+                let elder = self.role.as_elder().ok().cloned();
+                let network_api = self.network_api.clone();
+                let root_dir = self.node_info.root_dir.clone();
+                let encryption = self.node_info.encryption.clone();
+                let handle = tokio::spawn(async move {
+                    // A state not yet synced with our peers is not worth resuming from.
+                    if let Some(elder) = elder {
+                        if *elder.received_initial_sync.read().await {
+                            let written = ElderState::of(&elder, &network_api)
+                                .await
+                                .and_then(|state| state.write(&root_dir, encryption.as_ref()));
+                            if let Err(error) = written {
+                                warn!("Error writing Elder state: {:?}", error);
+                            }
+                        }
+                    }
+                    sleep(ELDER_STATE_WRITE_INTERVAL).await;
+                    Ok(NodeTask::from(vec![NodeDuty::WriteElderState]))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::CheckAdultLiveness => {
+                // Only Elders track the liveness of Adults, but we keep
+                // scheduling the checks as Adults too, in case we get promoted.
+                let elder = self.role.as_elder().ok().cloned();
+                let handle = tokio::spawn(async move {
+                    sleep(ADULT_LIVENESS_CHECK_INTERVAL).await;
+                    let mut ops = vec![];
+                    if let Some(elder) = elder {
+                        match elder.meta_data.write().await.check_adult_liveness().await {
+                            Ok(duties) => ops.extend(duties),
+                            Err(error) => warn!("Error checking Adult liveness: {:?}", error),
+                        }
+                    }
+                    ops.push(NodeDuty::CheckAdultLiveness);
+                    Ok(NodeTask::from(ops))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
             NodeDuty::ReachingMaxCapacity => {
                 let network_api = self.network_api.clone();
                 let handle = tokio::spawn(async move {
@@ -495,17 +608,72 @@ impl Node {
                 });
                 Ok(NodeTask::Thread(handle))
             }
+            NodeDuty::RenewStorageFull => {
+                // Only Adults report their storage full, but we keep
+                // scheduling the reports as Elders too, in case we get demoted.
+                let adult = self.role.as_adult().ok().cloned();
+                let network_api = self.network_api.clone();
+                let handle = tokio::spawn(async move {
+                    sleep(STORAGE_FULL_RENEW_INTERVAL).await;
+                    let mut ops = vec![];
+                    if let Some(adult) = adult {
+                        match adult.chunks.read().await.check_storage().await {
+                            Ok(duties) if !duties.is_empty() => {
+                                ops.extend(duties);
+                                // The other Adults only hear of it here, not on every
+                                // chunk read and write.
+                                ops.extend(Self::notify_adults_of_our_storage(&network_api).await?);
+                            }
+                            Ok(_) => (),
+                            Err(error) => warn!("Error checking used storage: {:?}", error),
+                        }
+                    }
+                    ops.push(NodeDuty::RenewStorageFull);
+                    Ok(NodeTask::from(ops))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::RetryChunkHandoffs => {
+                // Only Adults hand off chunks, but we keep scheduling the
+                // retries as Elders too, in case we get demoted.
+                let adult = self.role.as_adult().ok().cloned();
+                let handle = tokio::spawn(async move {
+                    sleep(CHUNK_HANDOFF_RETRY_INTERVAL).await;
+                    let mut ops = vec![];
+                    if let Some(adult) = adult {
+                        ops.extend(adult.retry_handoffs().await);
+                    }
+                    ops.push(NodeDuty::RetryChunkHandoffs);
+                    Ok(NodeTask::from(ops))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
             //
             // ------- Misc ------------
-            NodeDuty::IncrementFullNodeCount { node_id } => {
+            NodeDuty::IncrementFullNodeCount { node_id, origin } => {
+                if let Ok(adult) = self.role.as_adult() {
+                    // Adults only take the report of a node about itself.
+                    let name = XorName::from(node_id);
+                    if origin.name() == name {
+                        let _ = adult
+                            .storage_info
+                            .insert_full_adults(std::iter::once(name).collect())
+                            .await;
+                    }
+                    return Ok(NodeTask::None);
+                }
                 let elder = self.role.as_elder_mut()?.clone();
                 let handle = tokio::spawn(async move {
-                    elder
+                    let newly_full = elder
                         .meta_data
                         .write()
                         .await
                         .increase_full_node_count(node_id)
                         .await;
+                    if !newly_full {
+                        // Only the lease of the full node was renewed.
+                        return Ok(NodeTask::None);
+                    }
                     // Accept a new node in place for the full node.
                     Ok(NodeTask::from(vec![NodeDuty::SetNodeJoinsAllowed(true)]))
                 });
@@ -572,15 +740,13 @@ impl Node {
                 let elder = self.role.as_elder_mut()?.clone();
                 let handle = tokio::spawn(async move {
                     let duties = if network_api.our_prefix().await.matches(&data_section_addr) {
-                        vec![
-                            elder
-                                .meta_data
-                                // this is a write here as we write the liveness check for each adult
-                                .write()
-                                .await
-                                .read(query, msg_id, client_signed.public_key, origin)
-                                .await?,
-                        ]
+                        elder
+                            .meta_data
+                            // this is a write here as we write the liveness check for each adult
+                            .write()
+                            .await
+                            .read(query, msg_id, client_signed.public_key, origin)
+                            .await?
                     } else {
                         let targets = network_api
                             .get_closest_elders_to(
@@ -644,6 +810,37 @@ impl Node {
                 });
                 Ok(NodeTask::Thread(handle))
             }
+            NodeDuty::HedgeChunkRead { msg_id, delay } => {
+                let elder = self.role.as_elder()?.clone();
+                let handle = tokio::spawn(async move {
+                    sleep(delay).await;
+                    Ok(NodeTask::from(
+                        elder
+                            .meta_data
+                            .write()
+                            .await
+                            .hedge_chunk_read(msg_id)
+                            .await?,
+                    ))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::RecordAdultWriteLiveness {
+                result,
+                correlation_id,
+                src,
+            } => {
+                let elder = self.role.as_elder()?.clone();
+                let handle = tokio::spawn(async move {
+                    elder.meta_data.write().await.record_adult_write_liveness(
+                        correlation_id,
+                        result,
+                        src,
+                    );
+                    Ok(NodeTask::None)
+                });
+                Ok(NodeTask::Thread(handle))
+            }
             NodeDuty::ProcessDataPayment {
                 msg:
                     ProcessMsg::Cmd {
@@ -668,17 +865,70 @@ impl Node {
                 Ok(NodeTask::Thread(handle))
             }
             NodeDuty::ProcessDataPayment { .. } => Ok(NodeTask::None),
-            NodeDuty::ReplicateChunk { data, .. } => {
+            NodeDuty::ReplicateChunk {
+                data,
+                msg_id,
+                origin,
+            } => {
                 let adult = self.role.as_adult_mut()?.clone();
+                let network_api = self.network_api.clone();
                 let handle = tokio::spawn(async move {
-                    Ok(NodeTask::from(vec![
+                    let address = *data.address();
+                    let mut ops = vec![
                         adult
                             .chunks
                             .write()
                             .await
                             .store_for_replication(data)
                             .await?,
-                    ]))
+                    ];
+                    let adults = network_api.our_adults().await;
+                    let holders = adult
+                        .replication_ack_targets(&address, origin.name(), &adults)
+                        .await;
+                    ops.push(
+                        Self::acknowledge_replication(&network_api, address, msg_id, holders)
+                            .await?,
+                    );
+                    Ok(NodeTask::from(ops))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::RepublishChunkFallback {
+                address,
+                preceding,
+                targets,
+            } => {
+                let adult = self.role.as_adult()?.clone();
+                let network_api = self.network_api.clone();
+                let handle = tokio::spawn(async move {
+                    sleep(CHUNK_REPUBLISH_FALLBACK_TIMEOUT * preceding.len() as u32).await;
+                    let adults = network_api.our_adults().await;
+                    Ok(NodeTask::from(
+                        adult
+                            .republish_as_fallback(address, &preceding, targets, &adults)
+                            .await
+                            .into_iter()
+                            .collect::<Vec<_>>(),
+                    ))
+                });
+                Ok(NodeTask::Thread(handle))
+            }
+            NodeDuty::ConfirmChunkHandoff { address, holder } => {
+                // Elders republishing chunks get acks too, with nothing to confirm.
+                let adult = match self.role.as_adult() {
+                    Ok(adult) => adult.clone(),
+                    Err(_) => return Ok(NodeTask::None),
+                };
+                let handle = tokio::spawn(async move {
+                    Ok(NodeTask::from(
+                        adult
+                            .chunks
+                            .write()
+                            .await
+                            .confirm_handoff(&address, holder)
+                            .await?,
+                    ))
                 });
                 Ok(NodeTask::Thread(handle))
             }
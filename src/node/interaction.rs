@@ -11,10 +11,10 @@ use crate::{
     node_ops::{MsgType, NodeDuties, NodeDuty, OutgoingMsg},
     Node, Result,
 };
-use sn_data_types::{CreditAgreementProof, CreditId, PublicKey, SectionElders};
+use sn_data_types::{BlobAddress, CreditAgreementProof, CreditId, PublicKey, SectionElders};
 use sn_messaging::{
     node::{
-        NodeCmd, NodeMsg, NodeQueryResponse, NodeSystemCmd, NodeSystemQueryResponse,
+        NodeCmd, NodeEvent, NodeMsg, NodeQueryResponse, NodeSystemCmd, NodeSystemQueryResponse,
         NodeTransferCmd,
     },
     Aggregation, DstLocation, MessageId, SrcLocation,
@@ -74,7 +74,6 @@ impl Node {
         })])
     }
 
-    ///
     pub(crate) async fn notify_section_of_our_storage(network_api: &Network) -> Result<NodeDuty> {
         let node_id = PublicKey::from(network_api.public_key().await);
         Ok(NodeDuty::Send(OutgoingMsg {
@@ -91,6 +90,54 @@ impl Node {
         }))
     }
 
+    /// Tells the other Adults of our section our storage is full, for them to select the same
+    /// chunk holders as our Elders, see `AdultsStorageInfo`.
+    ///
+    /// Only sent with the periodic renewal of our report, not on every chunk read and write.
+    pub(crate) async fn notify_adults_of_our_storage(network_api: &Network) -> Result<NodeDuties> {
+        let node_id = PublicKey::from(network_api.public_key().await);
+        let mut adults = network_api.our_adults().await;
+        let _ = adults.remove(&network_api.our_name().await);
+        if adults.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(NodeDuties::from(NodeDuty::SendToNodes {
+            msg: NodeMsg::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::StorageFull {
+                    section: node_id.into(),
+                    node_id,
+                }),
+                id: MessageId::new(),
+            },
+            targets: adults,
+            aggregation: Aggregation::None,
+        }))
+    }
+
+    /// Acknowledges to `holders` that we stored the chunk at `address` sent us in `msg_id`.
+    ///
+    /// Only one of the holders handing the chunk off sends it, but all of them need our ack to let
+    /// go of it.
+    pub(crate) async fn acknowledge_replication(
+        network_api: &Network,
+        address: BlobAddress,
+        msg_id: MessageId,
+        holders: BTreeSet<XorName>,
+    ) -> Result<NodeDuty> {
+        Ok(NodeDuty::SendToNodes {
+            msg: NodeMsg::NodeEvent {
+                event: NodeEvent::ReplicationCompleted {
+                    chunk: address,
+                    proof: network_api.sign_as_node(&address).await?,
+                },
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+            },
+            targets: holders,
+            aggregation: Aggregation::None,
+        })
+    }
+
     ///
     pub(crate) async fn register_wallet(
         network_api: &Network,
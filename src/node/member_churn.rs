@@ -6,20 +6,26 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::role::{ElderRole, Role};
+use super::{
+    elder_state::ElderState,
+    role::{ElderRole, Role},
+};
 use crate::{
     capacity::{AdultsStorageInfo, Capacity, CapacityReader, CapacityWriter, StoreCost},
     metadata::{adult_reader::AdultReader, Metadata},
     network::Network,
     node_ops::NodeDuty,
-    section_funds::{reward_wallets::RewardWallets, SectionFunds},
+    section_funds::{
+        elder_signing::ElderSigning, reward_process::RewardProcess, reward_wallets::RewardWallets,
+        SectionFunds,
+    },
     transfers::{
         get_replicas::{replica_info, transfer_replicas},
         Transfers,
     },
     Node, Result,
 };
-use log::info;
+use log::{info, warn};
 use sn_data_types::{ActorHistory, NodeAge, PublicKey};
 use sn_messaging::client::DataExchange;
 use sn_routing::XorName;
@@ -35,40 +41,97 @@ impl Node {
     }
 
     /// Level up a newbie to an oldie on promotion
+    ///
+    /// If we were already an Elder of our section before a restart, we resume from the state we
+    /// stored then. Our peers still sync their full state to us, which we wait for, as it holds
+    /// what we missed while down; only merging it skips what we already have, see
+    /// `Replicas::merge`.
     pub async fn level_up(&mut self) -> Result<()> {
         self.used_space.reset().await?;
+        let stored_state = self.stored_elder_state().await;
 
         let adult_storage_info = AdultsStorageInfo::new();
         let adult_reader = AdultReader::new(self.network_api.clone());
         let capacity_reader = CapacityReader::new(adult_storage_info.clone(), adult_reader.clone());
         let capacity_writer = CapacityWriter::new(adult_storage_info.clone(), adult_reader.clone());
         let capacity = Capacity::new(capacity_reader.clone(), capacity_writer);
+        if let Some(state) = &stored_state {
+            let _ = capacity.insert_full_adults(state.full_adults.clone()).await;
+        }
 
         //
         // start handling metadata
         let max_capacity = self.used_space.max_capacity().await;
-        let meta_data =
-            Metadata::new(&self.node_info.path(), max_capacity, capacity.clone()).await?;
+        let meta_data = Metadata::new(
+            &self.node_info.path(),
+            max_capacity,
+            self.node_info.chunk_compression,
+            self.node_info.encryption.clone(),
+            capacity.clone(),
+            self.node_info.chunk_read_strategy,
+            self.node_info.chunk_cache_size,
+        )
+        .await?;
 
         //
         // start handling transfers
         let store_cost = StoreCost::new(self.network_api.clone(), capacity_reader);
         let user_wallets = BTreeMap::<PublicKey, ActorHistory>::new();
         let replicas = transfer_replicas(&self.node_info, &self.network_api, user_wallets).await?;
+        if stored_state.is_some() {
+            replicas.load_stored().await?;
+        }
         let transfers = Transfers::new(replicas, store_cost);
 
         //
         // start handling node rewards
-        let section_funds = SectionFunds::KeepingNodeWallets(RewardWallets::new(BTreeMap::<
-            XorName,
-            (NodeAge, PublicKey),
-        >::new()));
+        let section_funds = match stored_state {
+            None => SectionFunds::KeepingNodeWallets(RewardWallets::new(BTreeMap::<
+                XorName,
+                (NodeAge, PublicKey),
+            >::new())),
+            Some(state) => {
+                let wallets = RewardWallets::new(state.node_wallets);
+                match state.reward_process {
+                    None => SectionFunds::KeepingNodeWallets(wallets),
+                    Some((section, stage)) => SectionFunds::Churning {
+                        process: RewardProcess::resume(
+                            section,
+                            stage,
+                            ElderSigning::new(self.network_api.clone()).await?,
+                        ),
+                        wallets,
+                    },
+                }
+            }
+        };
 
+        // Not synced even when resumed, as the stored state lacks what we missed while down.
         self.role = Role::Elder(ElderRole::new(meta_data, transfers, section_funds, false));
 
         Ok(())
     }
 
+    // The Elder state stored before a restart, if it is of our current section.
+    async fn stored_elder_state(&self) -> Option<ElderState> {
+        match ElderState::read(self.node_info.path(), self.node_info.encryption.as_ref()) {
+            Ok(Some(state)) => {
+                if state.is_of_section(&self.network_api.section_chain().await) {
+                    info!("Resuming as Elder from stored state");
+                    Some(state)
+                } else {
+                    info!("Ignoring stored Elder state of another section");
+                    None
+                }
+            }
+            Ok(None) => None,
+            Err(error) => {
+                warn!("Error reading stored Elder state: {:?}", error);
+                None
+            }
+        }
+    }
+
     /// Continue the level up and handle more responsibilities.
     pub(crate) async fn synch_state(
         elder: &ElderRole,
@@ -6,6 +6,7 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod elder_state;
 mod handle;
 mod interaction;
 mod member_churn;
@@ -14,15 +15,19 @@ mod role;
 mod split;
 
 use crate::{
+    capacity::AdultsStorageInfo,
     chunk_store::UsedSpace,
     chunks::Chunks,
+    encryption::EncryptionKey,
     error::convert_to_error_message,
     event_mapping::{map_routing_event, Mapping, MsgContext},
+    migrations,
     network::Network,
     node_ops::{MsgType, NodeDuty, OutgoingLazyError},
     state_db::{get_reward_pk, store_new_reward_keypair},
-    Config, Error, Result,
+    ChunkReadStrategy, Config, Error, Result,
 };
+pub(crate) use elder_state::ELDER_STATE_FILENAME;
 use futures::{future::BoxFuture, lock::Mutex, stream::FuturesUnordered, FutureExt, StreamExt};
 use handle::NodeTask;
 use log::{error, warn};
@@ -49,6 +54,16 @@ pub struct NodeInfo {
     pub root_dir: PathBuf,
     /// The key used by the node to receive earned rewards.
     pub reward_key: PublicKey,
+    /// Number of chunks re-validated per second while an Adult.
+    pub chunk_scrub_rate: usize,
+    /// Whether chunks are compressed when stored.
+    pub chunk_compression: bool,
+    /// How chunks are read from their holders while an Elder.
+    pub chunk_read_strategy: ChunkReadStrategy,
+    /// Size in bytes of the cache of public chunks while an Elder, 0 if disabled.
+    pub chunk_cache_size: u64,
+    /// Key the node's local stores are encrypted with, if any.
+    pub encryption: Option<EncryptionKey>,
 }
 
 impl NodeInfo {
@@ -73,28 +88,44 @@ impl Node {
         let root_dir = root_dir_buf.as_path();
         std::fs::create_dir_all(root_dir)?;
 
+        let mut encryption = EncryptionKey::load(root_dir, config.encryption_secret()?.as_deref())?;
+        migrations::run(root_dir, encryption.as_mut())?;
+
         let reward_key = match get_reward_pk(root_dir).await? {
             Some(public_key) => PublicKey::Ed25519(public_key),
             None => {
                 let mut rng = OsRng;
                 let keypair = ed25519_dalek::Keypair::generate(&mut rng);
-                store_new_reward_keypair(root_dir, &keypair).await?;
+                store_new_reward_keypair(root_dir, &keypair, encryption.as_ref()).await?;
                 PublicKey::Ed25519(keypair.public)
             }
         };
 
-        let (network_api, network_events) = Network::new(root_dir, config).await?;
+        let (network_api, network_events) =
+            Network::new(root_dir, config, encryption.as_ref()).await?;
 
         let node_info = NodeInfo {
             root_dir: root_dir_buf,
             reward_key,
+            chunk_scrub_rate: config.chunk_scrub_rate() as usize,
+            chunk_compression: config.chunk_compression(),
+            chunk_read_strategy: config.chunk_read_strategy(),
+            chunk_cache_size: config.chunk_cache_size(),
+            encryption,
         };
 
         let node = Self {
             role: Role::Adult(AdultRole {
                 chunks: Arc::new(RwLock::new(
-                    Chunks::new(node_info.root_dir.as_path(), config.max_capacity()).await?,
+                    Chunks::new(
+                        node_info.root_dir.as_path(),
+                        config.max_capacity(),
+                        node_info.chunk_compression,
+                        node_info.encryption.clone(),
+                    )
+                    .await?,
                 )),
+                storage_info: AdultsStorageInfo::new(),
             }),
             node_info,
             used_space: UsedSpace::new(config.max_capacity()),
@@ -150,6 +181,28 @@ impl Node {
         ));
         let mut threads = FuturesUnordered::new();
         threads.push(routing_task_handle);
+        if self.node_info.chunk_scrub_rate > 0 {
+            threads.extend(
+                self.handle_and_get_threads(NodeDuty::ScrubChunks, None)
+                    .await,
+            );
+        }
+        threads.extend(
+            self.handle_and_get_threads(NodeDuty::RenewStorageFull, None)
+                .await,
+        );
+        threads.extend(
+            self.handle_and_get_threads(NodeDuty::RetryChunkHandoffs, None)
+                .await,
+        );
+        threads.extend(
+            self.handle_and_get_threads(NodeDuty::WriteElderState, None)
+                .await,
+        );
+        threads.extend(
+            self.handle_and_get_threads(NodeDuty::CheckAdultLiveness, None)
+                .await,
+        );
         while let Some(result) = threads.next().await {
             match result {
                 Ok(Ok(NodeTask::Thread(handle))) => threads.push(handle),
@@ -7,7 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    capacity::CHUNK_COPY_COUNT,
+    capacity::AdultsStorageInfo,
     chunks::Chunks,
     node_ops::{NodeDuties, NodeDuty},
 };
@@ -15,11 +15,11 @@ use itertools::Itertools;
 use log::{info, trace, warn};
 use sn_data_types::{Blob, BlobAddress};
 use sn_messaging::{
-    node::{NodeCmd, NodeMsg, NodeSystemCmd},
+    node::{NodeCmd, NodeMsg, NodeQuery, NodeSystemCmd, NodeSystemQuery},
     Aggregation, MessageId,
 };
 use sn_routing::XorName;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -27,6 +27,8 @@ use tokio::sync::RwLock;
 pub(crate) struct AdultRole {
     // immutable chunks
     pub chunks: Arc<RwLock<Chunks>>,
+    // the full adults, as reported by the other Adults
+    pub storage_info: AdultsStorageInfo,
 }
 
 impl AdultRole {
@@ -38,26 +40,108 @@ impl AdultRole {
         remaining: BTreeSet<XorName>,
     ) -> NodeDuties {
         let keys = self.chunks.read().await.keys();
-        let mut data_for_replication = BTreeMap::new();
+        let mut ops = vec![];
         for addr in keys.iter() {
-            if let Some((data, holders)) = self
-                .republish_and_cache(addr, &our_name, &new_adults, &lost_adults, &remaining)
-                .await
-            {
-                let _ = data_for_replication.insert(data, holders);
-            }
+            ops.extend(
+                self.republish_and_cache(addr, &our_name, &new_adults, &lost_adults, &remaining)
+                    .await,
+            );
         }
-        data_for_replication
+        ops
+    }
+
+    /// Sends the chunks we are handing off again to the new holders yet to acknowledge them.
+    pub async fn retry_handoffs(&self) -> NodeDuties {
+        self.chunks
+            .write()
+            .await
+            .due_handoffs()
             .into_iter()
-            .map(|(data, targets)| NodeDuty::SendToNodes {
-                msg: NodeMsg::NodeCmd {
-                    cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(data)),
-                    id: MessageId::new(),
-                },
-                targets,
-                aggregation: Aggregation::None,
+            .map(|(data, targets)| {
+                info!(
+                    "Handing off chunk {:?} again to {:?}",
+                    data.address(),
+                    targets
+                );
+                Self::replicate(data, targets)
             })
-            .collect::<Vec<_>>()
+            .collect()
+    }
+
+    /// Sends the chunk at `address` to `targets` ourselves, unless one of the holders elected
+    /// before us to send it is still in the section to do so.
+    pub async fn republish_as_fallback(
+        &self,
+        address: BlobAddress,
+        preceding: &[XorName],
+        targets: BTreeSet<XorName>,
+        adults: &BTreeSet<XorName>,
+    ) -> Option<NodeDuty> {
+        if preceding.iter().any(|sender| adults.contains(sender)) {
+            return None;
+        }
+        let chunk = self.chunks.read().await.get_chunk(&address).ok()?;
+        info!(
+            "Republishing chunk at {:?} in place of {:?}",
+            address, preceding
+        );
+        Some(Self::replicate(chunk, targets))
+    }
+
+    /// Returns the adults to acknowledge storing the chunk at `address` to: `origin` that sent it,
+    /// and every holder of it displaced by churn, which hands it off too without sending it.
+    pub async fn replication_ack_targets(
+        &self,
+        address: &BlobAddress,
+        origin: XorName,
+        adults: &BTreeSet<XorName>,
+    ) -> BTreeSet<XorName> {
+        let holders = self.compute_holders(address, adults).await;
+        let mut targets = self
+            .storage_info
+            .displaced_holders(address.name(), adults, &holders)
+            .await;
+        let _ = targets.insert(origin);
+        targets
+    }
+
+    /// Asks the other holders of the chunk at `address` for a good copy of it, as ours was found
+    /// corrupt. Their responses are handled by `Chunks::restore`.
+    pub async fn request_restore(
+        &self,
+        address: BlobAddress,
+        our_name: &XorName,
+        adults: &BTreeSet<XorName>,
+    ) -> Option<NodeDuty> {
+        let mut targets = self.compute_holders(&address, adults).await;
+        let _ = targets.remove(our_name);
+        if targets.is_empty() {
+            warn!("No other holders to restore chunk {:?} from", address);
+            return None;
+        }
+        info!(
+            "Restoring corrupt chunk {:?} from holders {:?}",
+            address, targets
+        );
+        Some(NodeDuty::SendToNodes {
+            msg: NodeMsg::NodeQuery {
+                query: NodeQuery::System(NodeSystemQuery::GetChunk(address)),
+                id: MessageId::new(),
+            },
+            targets,
+            aggregation: Aggregation::None,
+        })
+    }
+
+    fn replicate(data: Blob, targets: BTreeSet<XorName>) -> NodeDuty {
+        NodeDuty::SendToNodes {
+            msg: NodeMsg::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(data)),
+                id: MessageId::new(),
+            },
+            targets,
+            aggregation: Aggregation::None,
+        }
     }
 
     async fn republish_and_cache(
@@ -67,42 +151,134 @@ impl AdultRole {
         new_adults: &BTreeSet<XorName>,
         lost_adults: &BTreeSet<XorName>,
         remaining: &BTreeSet<XorName>,
-    ) -> Option<(Blob, BTreeSet<XorName>)> {
+    ) -> Option<NodeDuty> {
         let old_adult_list = remaining.union(lost_adults).copied().collect();
         let new_adult_list = remaining.union(new_adults).copied().collect();
-        let new_holders = self.compute_holders(addr, &new_adult_list);
-        let old_holders = self.compute_holders(addr, &old_adult_list);
+        let new_holders = self.compute_holders(addr, &new_adult_list).await;
+        let old_holders = self.compute_holders(addr, &old_adult_list).await;
 
         let we_are_not_holder_anymore = !new_holders.contains(our_name);
         let new_adult_is_holder = !new_holders.is_disjoint(new_adults);
         let lost_old_holder = !old_holders.is_disjoint(lost_adults);
 
-        if we_are_not_holder_anymore || new_adult_is_holder || lost_old_holder {
-            info!("Republishing chunk at {:?}", addr);
-            trace!("We are not a holder anymore? {}, New Adult is Holder? {}, Lost Adult was holder? {}", we_are_not_holder_anymore, new_adult_is_holder, lost_old_holder);
-            let chunk = self.chunks.read().await.get_chunk(addr).ok()?;
-            if we_are_not_holder_anymore {
-                if let Err(err) = self.chunks.write().await.remove_chunk(addr).await {
-                    warn!("Error deleting chunk during republish: {:?}", err);
-                }
+        if !we_are_not_holder_anymore {
+            if let Err(err) = self.chunks.write().await.cancel_handoff(addr) {
+                warn!("Error cancelling handoff of chunk: {:?}", err);
             }
-            // TODO: Push to LRU cache
-            Some((chunk, new_holders))
-        } else {
+        }
+
+        if !(we_are_not_holder_anymore || new_adult_is_holder || lost_old_holder) {
+            return None;
+        }
+        trace!(
+            "We are not a holder anymore? {}, New Adult is Holder? {}, Lost Adult was holder? {}",
+            we_are_not_holder_anymore,
+            new_adult_is_holder,
+            lost_old_holder
+        );
+        let (senders, mut targets) =
+            republish_plan(addr, our_name, &old_holders, &new_holders, remaining);
+        if targets.is_empty() {
+            if !we_are_not_holder_anymore {
+                return None;
+            }
+            // All new holders should have it already, but we only let go of it on their acks.
+            targets = new_holders;
+        }
+        let chunk = self.chunks.read().await.get_chunk(addr).ok()?;
+        // The chunk is only removed once enough of the new holders acknowledged storing it.
+        if we_are_not_holder_anymore {
+            if let Err(err) = self.chunks.write().await.hand_off(*addr, targets.clone()) {
+                warn!("Error handing off chunk during republish: {:?}", err);
+            }
+        }
+        // TODO: Push to LRU cache
+        let rank = senders.iter().position(|sender| sender == our_name)?;
+        if rank == 0 {
+            info!("Republishing chunk at {:?} to {:?}", addr, targets);
+            Some(Self::replicate(chunk, targets))
+        } else if we_are_not_holder_anymore {
+            // Our handoff is sent again after a timeout, unless acknowledged by then.
             None
+        } else {
+            Some(NodeDuty::RepublishChunkFallback {
+                address: *addr,
+                preceding: senders[..rank].to_vec(),
+                targets,
+            })
         }
     }
 
-    fn compute_holders(
+    async fn compute_holders(
         &self,
         addr: &BlobAddress,
         adult_list: &BTreeSet<XorName>,
     ) -> BTreeSet<XorName> {
-        adult_list
+        self.storage_info
+            .chunk_holders(addr.name(), adult_list)
+            .await
+    }
+}
+
+/// Returns the holders of the chunk at `addr` to send it to the holders new to it, in the order
+/// they are elected to, with those new holders.
+///
+/// The holders still in the section have the chunk, so each of them could send it. The one
+/// elected first sends it right away, and the others only as fallbacks after a timeout, so that
+/// the chunk is sent once rather than by every holder. Holders handing the chunk off come first, as
+/// they need the acks of the new holders to let go of it, and then the holders closest to the
+/// chunk.
+fn republish_plan(
+    addr: &BlobAddress,
+    our_name: &XorName,
+    old_holders: &BTreeSet<XorName>,
+    new_holders: &BTreeSet<XorName>,
+    remaining: &BTreeSet<XorName>,
+) -> (Vec<XorName>, BTreeSet<XorName>) {
+    let mut senders: BTreeSet<XorName> = old_holders.intersection(remaining).copied().collect();
+    let _ = senders.insert(*our_name);
+    let targets = new_holders.difference(&senders).copied().collect();
+    let senders = senders
+        .into_iter()
+        .sorted_by(|lhs, rhs| {
+            new_holders
+                .contains(lhs)
+                .cmp(&new_holders.contains(rhs))
+                .then_with(|| addr.name().cmp_distance(lhs, rhs))
+        })
+        .collect();
+    (senders, targets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::republish_plan;
+    use sn_data_types::{Blob, PublicBlob};
+    use sn_routing::XorName;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn elects_single_sender_for_new_holders_only() {
+        let addr = *Blob::Public(PublicBlob::new(b"chunk".to_vec())).address();
+        let holders: Vec<_> = (0..4).map(|_| XorName::random()).collect();
+        let joined = XorName::random();
+        let old_holders: BTreeSet<_> = holders.iter().copied().collect();
+        // The joined adult displaced the last old holder.
+        let new_holders: BTreeSet<_> = holders[..3].iter().copied().chain(Some(joined)).collect();
+        let remaining = old_holders.clone();
+
+        let plans: Vec<_> = holders
             .iter()
-            .sorted_by(|lhs, rhs| addr.name().cmp_distance(lhs, rhs))
-            .take(CHUNK_COPY_COUNT)
-            .cloned()
-            .collect()
+            .map(|holder| republish_plan(&addr, holder, &old_holders, &new_holders, &remaining))
+            .collect();
+        for (senders, targets) in &plans {
+            // All holders agree on the order, with the displaced holder first.
+            assert_eq!(senders, &plans[0].0);
+            assert_eq!(senders[0], holders[3]);
+            assert_eq!(targets, &Some(joined).into_iter().collect());
+        }
+        let mut rest = plans[0].0[1..].to_vec();
+        rest.sort_by(|lhs, rhs| addr.name().cmp_distance(lhs, rhs));
+        assert_eq!(rest, plans[0].0[1..].to_vec());
     }
 }
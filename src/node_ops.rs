@@ -9,13 +9,13 @@
 #[cfg(feature = "simulated-payouts")]
 use sn_data_types::Transfer;
 use sn_data_types::{
-    ActorHistory, Blob, CreditAgreementProof, NodeAge, PublicKey, RewardAccumulation,
+    ActorHistory, Blob, BlobAddress, CreditAgreementProof, NodeAge, PublicKey, RewardAccumulation,
     RewardProposal, SignedTransfer, TransferAgreementProof,
 };
 use sn_messaging::client::ClientMsg;
 use sn_messaging::{
     client::{
-        BlobRead, BlobWrite, ClientSigned, DataCmd, DataExchange, DataQuery, ProcessMsg,
+        BlobRead, BlobWrite, ClientSigned, CmdError, DataCmd, DataExchange, DataQuery, ProcessMsg,
         ProcessingError, QueryResponse, SupportingInfo,
     },
     node::NodeMsg,
@@ -25,6 +25,7 @@ use sn_routing::Prefix;
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::{Debug, Formatter},
+    time::Duration,
 };
 use xor_name::XorName;
 
@@ -92,10 +93,34 @@ pub enum NodeDuty {
         msg_id: MessageId,
         client_signed: ClientSigned,
     },
+    /// Run at Elders republishing a chunk got from its holders,
+    /// or at Adults restoring their corrupt copy of it.
     ProcessRepublish {
         chunk: Blob,
         msg_id: MessageId,
     },
+    /// Run at Adults, returning a copy of a chunk
+    /// for Elders to restore it at another holder.
+    GetChunkForRepublish {
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: SrcLocation,
+    },
+    /// Re-validate the content hash of the next
+    /// batch of chunks stored, when we are an Adult.
+    ScrubChunks,
+    /// Report our storage full to our section again
+    /// while it is, for it to keep counting us as full.
+    RenewStorageFull,
+    /// Send the chunks we are handing off again to
+    /// the new holders yet to acknowledge them.
+    RetryChunkHandoffs,
+    /// Write our Elder state to disk, for
+    /// resuming from it after a restart.
+    WriteElderState,
+    /// Penalise the Adults for the reads they did not respond
+    /// to in time, and propose the unresponsive ones as offline.
+    CheckAdultLiveness,
     /// Run at data-section Elders on receiving the result of
     /// read operations from Adults
     RecordAdultReadLiveness {
@@ -103,6 +128,19 @@ pub enum NodeDuty {
         correlation_id: MessageId,
         src: XorName,
     },
+    /// Query the next holder for a chunk read after `delay`,
+    /// unless one of those queried responded by then.
+    HedgeChunkRead {
+        msg_id: MessageId,
+        delay: Duration,
+    },
+    /// Run at data-section Elders on receiving the acks
+    /// of chunk writes from Adults
+    RecordAdultWriteLiveness {
+        result: Result<(), CmdError>,
+        correlation_id: MessageId,
+        src: XorName,
+    },
     /// Get section elders.
     GetSectionElders {
         msg_id: MessageId,
@@ -190,6 +228,8 @@ pub enum NodeDuty {
     IncrementFullNodeCount {
         /// Node ID of node that reached max capacity.
         node_id: PublicKey,
+        /// The node reporting it.
+        origin: SrcLocation,
     },
     /// Sets joining allowed to true or false.
     SetNodeJoinsAllowed(bool),
@@ -227,10 +267,26 @@ pub enum NodeDuty {
         origin: EndUser,
     },
     /// Receive a chunk that is being replicated.
-    /// This is run at an Adult (the new holder).
+    /// This is run at an Adult (the new holder),
+    /// acknowledging it to the sender once stored.
     ReplicateChunk {
         data: Blob,
         msg_id: MessageId,
+        origin: SrcLocation,
+    },
+    /// Republish a chunk after a timeout, at an Adult holding it,
+    /// unless any of the holders elected before it is still around to.
+    RepublishChunkFallback {
+        address: BlobAddress,
+        /// The holders elected before us, in order.
+        preceding: Vec<XorName>,
+        targets: BTreeSet<XorName>,
+    },
+    /// Run at the Adult handing off a chunk, on
+    /// a new holder acknowledging storing it.
+    ConfirmChunkHandoff {
+        address: BlobAddress,
+        holder: XorName,
     },
     /// Create proposals to vote unresponsive nodes as offline
     ProposeOffline(Vec<XorName>),
@@ -264,6 +320,12 @@ impl Debug for NodeDuty {
             Self::ReadChunk { .. } => write!(f, "ReadChunk"),
             Self::WriteChunk { .. } => write!(f, "WriteChunk"),
             Self::ProcessRepublish { .. } => write!(f, "ProcessRepublish"),
+            Self::GetChunkForRepublish { .. } => write!(f, "GetChunkForRepublish"),
+            Self::ScrubChunks => write!(f, "ScrubChunks"),
+            Self::RetryChunkHandoffs => write!(f, "RetryChunkHandoffs"),
+            Self::RenewStorageFull => write!(f, "RenewStorageFull"),
+            Self::WriteElderState => write!(f, "WriteElderState"),
+            Self::CheckAdultLiveness => write!(f, "CheckAdultLiveness"),
             Self::RecordAdultReadLiveness {
                 correlation_id,
                 response,
@@ -273,6 +335,20 @@ impl Debug for NodeDuty {
                 "RecordAdultReadLiveness {{ correlation_id: {}, response: {:?}, src: {} }}",
                 correlation_id, response, src
             ),
+            Self::HedgeChunkRead { msg_id, delay } => write!(
+                f,
+                "HedgeChunkRead {{ msg_id: {}, delay: {:?} }}",
+                msg_id, delay
+            ),
+            Self::RecordAdultWriteLiveness {
+                correlation_id,
+                result,
+                src,
+            } => write!(
+                f,
+                "RecordAdultWriteLiveness {{ correlation_id: {}, result: {:?}, src: {} }}",
+                correlation_id, result, src
+            ),
             Self::ReceiveRewardProposal { .. } => write!(f, "ReceiveRewardProposal"),
             Self::ReceiveRewardAccumulation { .. } => write!(f, "ReceiveRewardAccumulation"),
             // ------
@@ -304,6 +380,8 @@ impl Debug for NodeDuty {
             Self::ProcessWrite { .. } => write!(f, "ProcessWrite"),
             Self::ProcessDataPayment { .. } => write!(f, "ProcessDataPayment"),
             Self::ReplicateChunk { .. } => write!(f, "ReplicateChunk"),
+            Self::RepublishChunkFallback { .. } => write!(f, "RepublishChunkFallback"),
+            Self::ConfirmChunkHandoff { .. } => write!(f, "ConfirmChunkHandoff"),
             Self::ProposeOffline(nodes) => write!(f, "ProposeOffline({:?})", nodes),
         }
     }
@@ -20,6 +20,7 @@ use crate::{
     Error, Result,
 };
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
 use sn_data_types::{
     Credit, NodeAge, PublicKey, RewardAccumulation, RewardProposal, Signature, Signing, Token,
 };
@@ -39,7 +40,7 @@ pub struct RewardProcess {
 }
 
 ///
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OurSection {
     ///
     pub our_prefix: Prefix,
@@ -68,6 +69,19 @@ impl RewardProcess {
         }
     }
 
+    /// Resumes a process from the stage it had reached, e.g. before a restart.
+    pub fn resume(section: OurSection, stage: RewardStage, signing: ElderSigning) -> Self {
+        Self {
+            section,
+            signing,
+            stage,
+        }
+    }
+
+    pub fn section(&self) -> &OurSection {
+        &self.section
+    }
+
     pub fn stage(&self) -> &RewardStage {
         &self.stage
     }
@@ -8,13 +8,14 @@
 
 use crate::{Error, Result};
 use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use sn_data_types::{
     Credit, CreditAgreementProof, CreditId, PublicKey, ReplicaPublicKeySet, SignatureShare,
     SignedCredit, SignedCreditShare,
 };
 use std::collections::BTreeMap;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum RewardStage {
     AwaitingThreshold,
@@ -23,13 +24,13 @@ pub enum RewardStage {
     Completed(BTreeMap<CreditId, CreditAgreementProof>),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RewardProposalDetails {
     pub pk_set: ReplicaPublicKeySet,
     pub rewards: BTreeMap<CreditId, CreditProposal>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CreditProposal {
     pub proposal: Credit,
     pub signatures: BTreeMap<usize, bls::SignatureShare>,
@@ -42,13 +43,13 @@ impl CreditProposal {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RewardAccumulationDetails {
     pub pk_set: ReplicaPublicKeySet,
     pub rewards: BTreeMap<CreditId, CreditAccumulation>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CreditAccumulation {
     pub agreed_proposal: SignedCredit,
     pub signatures: BTreeMap<usize, bls::SignatureShare>,
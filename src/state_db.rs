@@ -6,7 +6,10 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{Error, Result};
+use crate::{
+    encryption::{self, EncryptionKey},
+    Error, Result,
+};
 pub use ed25519_dalek::{Keypair, PublicKey, SecretKey, KEYPAIR_LENGTH, PUBLIC_KEY_LENGTH};
 use hex::{decode, encode};
 use std::path::Path;
@@ -15,28 +18,33 @@ use tokio::fs;
 // Filename for storing the node's reward (Ed25519 hex-encoded) public key
 const REWARD_PUBLIC_KEY_FILENAME: &str = "reward_public_key";
 // Filename for storing the node's reward (Ed25519 hex-encoded) secret key
-const REWARD_SECRET_KEY_FILENAME: &str = "reward_secret_key";
+pub(crate) const REWARD_SECRET_KEY_FILENAME: &str = "reward_secret_key";
 
-const NETWORK_KEYPAIR_FILENAME: &str = "network_keypair";
+pub(crate) const NETWORK_KEYPAIR_FILENAME: &str = "network_keypair";
 
-/// Writes the network keypair to disk.
+/// Writes the network keypair to disk, encrypted if an `encryption` key is given.
 pub async fn store_network_keypair(
     root_dir: &Path,
     keypair_as_bytes: [u8; KEYPAIR_LENGTH],
+    encryption: Option<&EncryptionKey>,
 ) -> Result<()> {
     let keypair_path = root_dir.join(NETWORK_KEYPAIR_FILENAME);
-    fs::write(keypair_path, encode(keypair_as_bytes)).await?;
+    let contents = encryption::encrypt(encryption, encode(keypair_as_bytes).into_bytes())?;
+    fs::write(keypair_path, contents).await?;
 
     Ok(())
 }
 
 /// Returns Some(KeyPair) or None if file doesn't exist.
-pub async fn get_network_keypair(root_dir: &Path) -> Result<Option<Keypair>> {
+pub async fn get_network_keypair(
+    root_dir: &Path,
+    encryption: Option<&EncryptionKey>,
+) -> Result<Option<Keypair>> {
     let path = root_dir.join(NETWORK_KEYPAIR_FILENAME);
     if !path.is_file() {
         return Ok(None);
     }
-    let keypair_hex_bytes = fs::read(path).await?;
+    let keypair_hex_bytes = encryption::decrypt(encryption, fs::read(path).await?)?;
     let keypair_bytes = decode(keypair_hex_bytes).map_err(|err| {
         Error::Logic(format!(
             "Couldn't hex-decode network keypair bytes: {}",
@@ -50,11 +58,18 @@ pub async fn get_network_keypair(root_dir: &Path) -> Result<Option<Keypair>> {
     Ok(Some(keypair))
 }
 
-/// Writes the public and secret key (hex-encoded) to different locations at disk.
-pub async fn store_new_reward_keypair(root_dir: &Path, keypair: &Keypair) -> Result<()> {
+/// Writes the public and secret key (hex-encoded) to different locations at disk. The secret key is
+/// encrypted if an `encryption` key is given.
+pub async fn store_new_reward_keypair(
+    root_dir: &Path,
+    keypair: &Keypair,
+    encryption: Option<&EncryptionKey>,
+) -> Result<()> {
     let secret_key_path = root_dir.join(REWARD_SECRET_KEY_FILENAME);
     let public_key_path = root_dir.join(REWARD_PUBLIC_KEY_FILENAME);
-    fs::write(secret_key_path, encode(keypair.secret.to_bytes())).await?;
+    let secret_key =
+        encryption::encrypt(encryption, encode(keypair.secret.to_bytes()).into_bytes())?;
+    fs::write(secret_key_path, secret_key).await?;
     fs::write(public_key_path, encode(keypair.public.to_bytes())).await?;
 
     Ok(())
@@ -84,7 +99,9 @@ pub async fn get_reward_pk(root_dir: &Path) -> Result<Option<PublicKey>> {
 mod test {
     use super::{
         get_network_keypair, get_reward_pk, store_network_keypair, store_new_reward_keypair,
+        NETWORK_KEYPAIR_FILENAME,
     };
+    use crate::encryption::EncryptionKey;
     use anyhow::{anyhow, Result};
     use rand::{distributions::Alphanumeric, rngs::OsRng, thread_rng, Rng};
     use tempdir::TempDir;
@@ -96,7 +113,7 @@ mod test {
 
         let root = create_temp_root()?;
         let root_dir = root.path();
-        store_new_reward_keypair(root_dir, &keypair).await?;
+        store_new_reward_keypair(root_dir, &keypair, None).await?;
         let pk_result = get_reward_pk(root_dir).await?;
 
         assert_eq!(pk_result, Some(keypair.public));
@@ -111,11 +128,34 @@ mod test {
         let root = create_temp_root()?;
         let root_dir = root.path();
 
-        let keypair_result = get_network_keypair(root_dir).await?;
+        let keypair_result = get_network_keypair(root_dir, None).await?;
         assert!(keypair_result.is_none());
 
-        store_network_keypair(root_dir, keypair.to_bytes()).await?;
-        let keypair_result = get_network_keypair(root_dir).await?;
+        store_network_keypair(root_dir, keypair.to_bytes(), None).await?;
+        let keypair_result = get_network_keypair(root_dir, None).await?;
+        if let Some(kp) = keypair_result {
+            assert_eq!(kp.public, keypair.public);
+            Ok(())
+        } else {
+            Err(anyhow!("Network keypair was not read from file"))
+        }
+    }
+
+    #[tokio::test]
+    async fn encrypted_keypair_to_and_from_file() -> Result<()> {
+        let mut rng = OsRng;
+        let keypair = ed25519_dalek::Keypair::generate(&mut rng);
+        let encryption = EncryptionKey::random();
+
+        let root = create_temp_root()?;
+        let root_dir = root.path();
+
+        store_network_keypair(root_dir, keypair.to_bytes(), Some(&encryption)).await?;
+        let contents = std::fs::read(root_dir.join(NETWORK_KEYPAIR_FILENAME))?;
+        assert_ne!(contents, hex::encode(keypair.to_bytes()).into_bytes());
+        assert!(get_network_keypair(root_dir, None).await.is_err());
+
+        let keypair_result = get_network_keypair(root_dir, Some(&encryption)).await?;
         if let Some(kp) = keypair_result {
             assert_eq!(kp.public, keypair.public);
             Ok(())
@@ -17,8 +17,9 @@ pub async fn transfer_replicas(
     user_wallets: BTreeMap<PublicKey, ActorHistory>,
 ) -> Result<Replicas<ReplicaSigningImpl>> {
     let root_dir = node_info.root_dir.clone();
+    let encryption = node_info.encryption.clone();
     let info = replica_info(network).await?;
-    Replicas::new(root_dir, info, user_wallets).await
+    Replicas::new(root_dir, encryption, info, user_wallets).await
 }
 
 pub async fn replica_info(network: &Network) -> Result<ReplicaInfo<ReplicaSigningImpl>> {
@@ -6,8 +6,11 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{replica_signing::ReplicaSigning, store::TransferStore};
-use crate::{Error, Result};
+use super::{
+    replica_signing::ReplicaSigning,
+    store::{self, TransferStore},
+};
+use crate::{encryption::EncryptionKey, Error, Result};
 use bls::PublicKeySet;
 use dashmap::DashMap;
 use log::info;
@@ -17,7 +20,11 @@ use sn_data_types::{
     TransferAgreementProof, TransferPropagated, TransferRegistered, TransferValidated,
 };
 use sn_transfers::WalletReplica;
-use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 use tokio::sync::RwLock;
 use xor_name::Prefix;
 
@@ -51,6 +58,7 @@ where
     T: ReplicaSigning,
 {
     root_dir: PathBuf,
+    encryption: Option<EncryptionKey>,
     info: ReplicaInfo<T>,
     stores: Stores,
 }
@@ -58,11 +66,13 @@ where
 impl<T: ReplicaSigning> Replicas<T> {
     pub(crate) async fn new(
         root_dir: PathBuf,
+        encryption: Option<EncryptionKey>,
         info: ReplicaInfo<T>,
         user_wallets: BTreeMap<PublicKey, ActorHistory>,
     ) -> Result<Self> {
         let instance = Self {
             root_dir,
+            encryption,
             info,
             stores: DashMap::new(),
         };
@@ -70,8 +80,29 @@ impl<T: ReplicaSigning> Replicas<T> {
         Ok(instance)
     }
 
+    /// Merges in the histories of `user_wallets`, skipping the transfers we already have.
     pub async fn merge(&mut self, user_wallets: BTreeMap<PublicKey, ActorHistory>) -> Result<()> {
-        self.setup(user_wallets).await // TODO: fix this!!!! (this duplciates entries in db)
+        self.setup(user_wallets).await
+    }
+
+    /// Opens the stores of all wallets with events stored under our root dir, e.g. when resuming
+    /// as an Elder after a restart.
+    pub async fn load_stored(&self) -> Result<()> {
+        for id in store::stored_ids(&self.root_dir)? {
+            let store = TransferStore::new(id, &self.root_dir, self.encryption.clone())?;
+            let key = match Self::wallet_of(&store)? {
+                Some(key) => key,
+                None => continue,
+            };
+            let _ = self.stores.insert(key, Arc::new(RwLock::new(store)));
+        }
+        info!("Loaded {} stored transfer wallets", self.stores.len());
+        Ok(())
+    }
+
+    // The key of the wallet the events of the store are of.
+    fn wallet_of(store: &TransferStore<ReplicaEvent>) -> Result<Option<PublicKey>> {
+        Ok(wallet_key(&store.get_all()?))
     }
 
     async fn setup(&self, user_wallets: BTreeMap<PublicKey, ActorHistory>) -> Result<()> {
@@ -88,7 +119,13 @@ impl<T: ReplicaSigning> Replicas<T> {
                     "ActorHistory must contain only transfers of a single actor.".to_string(),
                 ));
             }
+            let known = self.history(node).await?;
+            let known_credits: HashSet<_> = known.credits.iter().map(|c| *c.id()).collect();
+            let known_debits: HashSet<_> = known.debits.iter().map(|d| d.id()).collect();
             for credit_proof in wallet.credits {
+                if known_credits.contains(credit_proof.id()) {
+                    continue;
+                }
                 let id = credit_proof.recipient();
                 let e = TransferPropagated(sn_data_types::TransferPropagated { credit_proof });
                 // Acquire lock of the store.
@@ -98,6 +135,9 @@ impl<T: ReplicaSigning> Replicas<T> {
                 store.try_insert(e.to_owned())?;
             }
             for transfer_proof in wallet.debits {
+                if known_debits.contains(&transfer_proof.id()) {
+                    continue;
+                }
                 let id = transfer_proof.sender();
                 let e = TransferRegistered(sn_data_types::TransferRegistered { transfer_proof });
                 // Acquire lock of the store.
@@ -144,7 +184,7 @@ impl<T: ReplicaSigning> Replicas<T> {
         // could be iterated in parallel
         for entry in &self.stores {
             let store = entry.value().read().await;
-            events.extend(store.get_all());
+            events.extend(store.get_all()?);
         }
 
         Ok(events)
@@ -160,8 +200,7 @@ impl<T: ReplicaSigning> Replicas<T> {
 
         // read lock is on
 
-        let events = store.get_all();
-
+        let events = store.get_all()?;
         if events.is_empty() {
             return Ok(ActorHistory::empty());
         }
@@ -387,7 +426,7 @@ impl<T: ReplicaSigning> Replicas<T> {
         store: &TransferStore<ReplicaEvent>,
         id: OwnerType,
     ) -> Result<WalletReplica> {
-        let events = store.get_all();
+        let events = store.get_all()?;
         let wallet = WalletReplica::from_history(
             id,
             self.info.id,
@@ -415,6 +454,7 @@ impl<T: ReplicaSigning> Replicas<T> {
                 .or_insert(Arc::new(RwLock::new(TransferStore::new(
                     key.into(),
                     &self.root_dir,
+                    self.encryption.clone(),
                 )?)));
         Ok((*store_ref).clone())
     }
@@ -478,3 +518,13 @@ impl<T: ReplicaSigning> Replicas<T> {
         Ok(NodeDuty::NoOp)
     }
 }
+
+/// Returns the key of the wallet the stored events are of.
+pub(crate) fn wallet_key(events: &[ReplicaEvent]) -> Option<PublicKey> {
+    events.first().map(|event| match event {
+        ReplicaEvent::TransferValidationProposed(e) => e.sender(),
+        ReplicaEvent::TransferValidated(e) => e.sender(),
+        ReplicaEvent::TransferRegistered(e) => e.sender(),
+        ReplicaEvent::TransferPropagated(e) => e.recipient(),
+    })
+}
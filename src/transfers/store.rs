@@ -6,33 +6,57 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{to_db_key::ToDbKey, utils, Error, Result};
-use pickledb::PickleDb;
+use crate::{
+    encryption::{self, EncryptionKey},
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use log::{error, info, warn};
+use pickledb::{PickleDb, PickleDbDumpPolicy};
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    collections::BTreeSet,
+    convert::TryInto,
     fmt::Debug,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
     marker::PhantomData,
     path::{Path, PathBuf},
 };
 use xor_name::XorName;
 
 const TRANSFERS_DIR_NAME: &str = "transfers";
+const LOG_EXTENSION: &str = ".log";
+/// Extension of the `PickleDb` files transfers were stored in before the event log.
 const DB_EXTENSION: &str = ".db";
+/// Length of the header of a record in the log: the length and CRC32 checksum of its payload, both
+/// as little endian `u32`s.
+const RECORD_HEADER_LEN: usize = 8;
 
 /// Disk storage for transfers.
+///
+/// The events of a wallet are appended to a log file, each as a record made of a header and the
+/// serialised event. A record torn by a crash in the middle of an append fails its checksum, and is
+/// truncated off the log when it is next opened.
+///
+/// If an `encryption` key is set, events are stored encrypted. Events stored unencrypted before
+/// remain readable until they are encrypted by `encrypt_plaintext_logs`.
 pub struct TransferStore<TEvent: Debug + Serialize + DeserializeOwned> {
-    db: PickleDb,
-    db_path: PathBuf,
+    file: File,
+    log_path: PathBuf,
+    // Length in bytes of the valid records in the log.
+    log_len: u64,
+    encryption: Option<EncryptionKey>,
     _phantom: PhantomData<TEvent>,
 }
 
 pub struct DeletableStore {
-    db_path: PathBuf,
+    log_path: PathBuf,
 }
 
 impl DeletableStore {
     pub fn delete(&self) -> Result<()> {
-        std::fs::remove_file(self.db_path.as_path()).map_err(Error::Io)
+        fs::remove_file(self.log_path.as_path()).map_err(Error::Io)
     }
 }
 
@@ -40,73 +64,354 @@ impl<'a, TEvent: Debug + Serialize + DeserializeOwned> TransferStore<TEvent>
 where
     TEvent: 'a,
 {
-    pub fn new(id: XorName, root_dir: &Path) -> Result<Self> {
-        let db_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
-        let db_name = format!("{}{}", id.to_db_key()?, DB_EXTENSION);
-        let db_path = db_dir.join(db_name.clone());
+    /// Opens the event log of the wallet `id`, creating it if it doesn't exist yet.
+    ///
+    /// Events found in a `PickleDb` file of the wallet, as stored before the event log, are
+    /// migrated to the log on opening.
+    pub fn new(id: XorName, root_dir: &Path, encryption: Option<EncryptionKey>) -> Result<Self> {
+        let log_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
+        fs::create_dir_all(&log_dir)?;
+        let name = id.to_db_key()?;
+        let log_path = log_dir.join(format!("{}{}", name, LOG_EXTENSION));
+
+        let db_path = log_dir.join(format!("{}{}", name, DB_EXTENSION));
+        if db_path.is_file() {
+            Self::migrate(&db_path, &log_path, encryption.as_ref())?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        let (_, log_len) = read_records(&log_path)?;
+        if log_len < file.metadata()?.len() {
+            warn!(
+                "Truncating invalid records at the end of {}",
+                log_path.display()
+            );
+            file.set_len(log_len)?;
+            file.sync_all()?;
+        }
+
         Ok(Self {
-            db: utils::new_auto_dump_db(db_dir.as_path(), db_name)?,
-            db_path,
+            file,
+            log_path,
+            log_len,
+            encryption,
             _phantom: PhantomData::default(),
         })
     }
 
     pub fn as_deletable(&self) -> DeletableStore {
         DeletableStore {
-            db_path: self.db_path.clone(),
+            log_path: self.log_path.clone(),
         }
     }
 
+    /// Returns all events in the order they were inserted.
     ///
-    pub fn get_all(&self) -> Vec<TEvent> {
-        let keys = self.db.get_all();
-
-        let mut events: Vec<(usize, TEvent)> = keys
+    /// Fails if any of them can't be decrypted or decoded, rather than returning a partial history.
+    pub fn get_all(&self) -> Result<Vec<TEvent>> {
+        self.read_log()?
             .iter()
-            .filter_map(|key| {
-                let value = self.db.get::<TEvent>(key);
-                let key = key.parse::<usize>();
-                match value {
-                    Some(v) => match key {
-                        Ok(k) => Some((k, v)),
-                        _ => None,
-                    },
-                    None => None,
-                }
+            .enumerate()
+            .map(|(index, serialised)| {
+                utils::deserialise(serialised).map_err(|e| {
+                    error!(
+                        "Could not decode transfer event {} in {}: {}",
+                        index,
+                        self.log_path.display(),
+                        e
+                    );
+                    e
+                })
             })
-            .collect();
-
-        events.sort_by(|(key_a, _), (key_b, _)| key_a.partial_cmp(key_b).unwrap());
-
-        let events: Vec<TEvent> = events.into_iter().map(|(_, val)| val).collect();
-
-        events
+            .collect()
     }
 
-    ///
+    /// Appends `event` to the log. The event is synced to disk before returning.
     pub fn try_insert(&mut self, event: TEvent) -> Result<()> {
-        let key = &self.db.total_keys().to_string();
-        if self.db.exists(key) {
+        let payload =
+            encryption::encrypt(self.encryption.as_ref(), utils::serialise(&event)?.to_vec())?;
+        let record = encode_record(&payload)?;
+
+        let res = self
+            .file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = res {
+            // Don't leave a partial record behind for the next one to be appended to.
+            let _ = self.file.set_len(self.log_len);
             return Err(Error::Logic(format!(
-                "Key exists: {}. Event: {:?}",
-                key, event
+                "Could not append event {:?}: {}",
+                event, e
             )));
         }
-        self.db.set(key, &event).map_err(Error::PickleDb)
+        self.log_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn read_log(&self) -> Result<Vec<Vec<u8>>> {
+        read_log(&self.log_path, self.encryption.as_ref()).map_err(|e| {
+            error!(
+                "Could not read transfer events from {}: {}",
+                self.log_path.display(),
+                e
+            );
+            e
+        })
+    }
+
+    /// Moves the events of the `PickleDb` at `db_path` to a new log at `log_path`, then removes the
+    /// `PickleDb`. If there's already a log, the `PickleDb` is left over from an interrupted
+    /// migration and is only removed.
+    fn migrate(db_path: &Path, log_path: &Path, encryption: Option<&EncryptionKey>) -> Result<()> {
+        if !log_path.is_file() {
+            info!("Migrating {} to an event log", db_path.display());
+            let db = PickleDb::load_bin(db_path, PickleDbDumpPolicy::NeverDump)?;
+
+            let mut keys: Vec<(usize, String)> = db
+                .get_all()
+                .into_iter()
+                .filter_map(|key| Some((key.parse().ok()?, key)))
+                .collect();
+            keys.sort();
+
+            let mut contents = vec![];
+            for (_, key) in keys {
+                // Events are kept encrypted as they are.
+                let payload = match db
+                    .get::<Vec<u8>>(&key)
+                    .filter(|value| encryption::is_encrypted(value))
+                {
+                    Some(payload) => payload,
+                    None => match db.get::<TEvent>(&key) {
+                        Some(event) => {
+                            encryption::encrypt(encryption, utils::serialise(&event)?.to_vec())?
+                        }
+                        None => {
+                            return Err(Error::Logic(format!(
+                                "Could not migrate transfer event {} of {}",
+                                key,
+                                db_path.display()
+                            )))
+                        }
+                    },
+                };
+                contents.extend(encode_record(&payload)?);
+            }
+
+            utils::write_atomically(log_path, &contents)?;
+        }
+        fs::remove_file(db_path)?;
+        Ok(())
+    }
+}
+
+/// Migrates all the `PickleDb` files under `root_dir`, as transfers were stored in before the
+/// event log, to event logs. Does nothing for wallets already migrated.
+pub fn migrate_pickle_dbs<TEvent: Debug + Serialize + DeserializeOwned>(
+    root_dir: &Path,
+    encryption: Option<&EncryptionKey>,
+) -> Result<()> {
+    let log_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
+    if !log_dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&log_dir)? {
+        let db_path = entry?.path();
+        let name = match db_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.strip_suffix(DB_EXTENSION))
+        {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let log_path = log_dir.join(format!("{}{}", name, LOG_EXTENSION));
+        TransferStore::<TEvent>::migrate(&db_path, &log_path, encryption)?;
+    }
+    Ok(())
+}
+
+/// Encrypts with `key` the records of the event logs under `root_dir` stored before encryption
+/// was enabled. Logs are rewritten next to the current ones, dropping any torn record at their end
+/// as opening them would, and those without plaintext records are left as they are.
+pub fn encrypt_plaintext_logs(root_dir: &Path, key: &EncryptionKey) -> Result<()> {
+    let log_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
+    if !log_dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&log_dir)? {
+        let log_path = entry?.path();
+        if !log_path.to_string_lossy().ends_with(LOG_EXTENSION) {
+            continue;
+        }
+        let (payloads, _) = read_records(&log_path)?;
+        if payloads
+            .iter()
+            .all(|payload| encryption::is_encrypted(payload))
+        {
+            continue;
+        }
+
+        info!("Encrypting the transfer events in {}", log_path.display());
+        let mut contents = vec![];
+        for payload in payloads {
+            let payload = if encryption::is_encrypted(&payload) {
+                payload
+            } else {
+                key.encrypt(&payload)?
+            };
+            contents.extend(encode_record(&payload)?);
+        }
+        utils::write_atomically(&log_path, &contents)?;
+    }
+    Ok(())
+}
+
+/// Reads the log of the wallet `id` under `root_dir` without changing anything on disk, e.g. to
+/// inspect it while the node is stopped. Returns `None` if there is no log for the wallet.
+pub fn read_stored<TEvent: DeserializeOwned>(
+    id: XorName,
+    root_dir: &Path,
+    encryption: Option<&EncryptionKey>,
+) -> Result<Option<Vec<TEvent>>> {
+    let log_path = root_dir.join(Path::new(TRANSFERS_DIR_NAME)).join(format!(
+        "{}{}",
+        id.to_db_key()?,
+        LOG_EXTENSION
+    ));
+    if !log_path.is_file() {
+        return Ok(None);
+    }
+    let events = read_log(&log_path, encryption)?
+        .iter()
+        .map(|event| utils::deserialise(event))
+        .collect::<Result<_>>()?;
+    Ok(Some(events))
+}
+
+/// Lists the temp files left behind under `root_dir` by a migration interrupted by a crash.
+pub fn temp_files(root_dir: &Path) -> Result<Vec<PathBuf>> {
+    let log_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
+    if !log_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = vec![];
+    for entry in fs::read_dir(&log_dir)? {
+        let path = entry?.path();
+        if path.to_string_lossy().ends_with(utils::TEMP_FILE_SUFFIX) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Returns the ids of the wallets with events stored under `root_dir`.
+pub fn stored_ids(root_dir: &Path) -> Result<Vec<XorName>> {
+    let log_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
+    if !log_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut ids = BTreeSet::new();
+    for entry in fs::read_dir(&log_dir)? {
+        let file_name = entry?.file_name();
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+        let name = match file_name
+            .strip_suffix(LOG_EXTENSION)
+            .or_else(|| file_name.strip_suffix(DB_EXTENSION))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        match from_db_key(name) {
+            Ok(id) => {
+                let _ = ids.insert(id);
+            }
+            Err(e) => warn!("Ignoring unexpected file {} in transfers: {}", file_name, e),
+        }
+    }
+    Ok(ids.into_iter().collect())
+}
+
+// Reads the serialised events in the log at `log_path`. Fails at the first record that can't be
+// decrypted.
+fn read_log(log_path: &Path, encryption: Option<&EncryptionKey>) -> Result<Vec<Vec<u8>>> {
+    let (payloads, _) = read_records(log_path)?;
+    payloads
+        .into_iter()
+        .enumerate()
+        .map(|(index, payload)| {
+            encryption::decrypt(encryption, payload).map_err(|e| {
+                Error::Logic(format!(
+                    "Could not decrypt record {} of {}: {}",
+                    index,
+                    log_path.display(),
+                    e
+                ))
+            })
+        })
+        .collect()
+}
+
+fn encode_record(payload: &[u8]) -> Result<Vec<u8>> {
+    let len: u32 = payload
+        .len()
+        .try_into()
+        .map_err(|_| Error::Logic("Transfer event too large".to_string()))?;
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    Ok(record)
+}
+
+// Returns the payloads of the valid records in the log at `path`, and their total length in bytes
+// including headers. Reading stops at the first record that is incomplete or fails its checksum.
+fn read_records(path: &Path) -> Result<(Vec<Vec<u8>>, u64)> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((vec![], 0)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut payloads = vec![];
+    let mut pos = 0;
+    while contents.len() - pos >= RECORD_HEADER_LEN {
+        let header = &contents[pos..pos + RECORD_HEADER_LEN];
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        let start = pos + RECORD_HEADER_LEN;
+        if contents.len() - start < len {
+            break;
+        }
+        let payload = &contents[start..start + len];
+        if crc32fast::hash(payload) != checksum {
+            break;
+        }
+        payloads.push(payload.to_vec());
+        pos = start + len;
     }
+    Ok((payloads, pos as u64))
 }
 
 #[cfg(test)]
 mod test {
-    use super::TransferStore;
-    use crate::{Error, Result};
+    use super::{TransferStore, DB_EXTENSION, TRANSFERS_DIR_NAME};
+    use crate::{encryption::EncryptionKey, to_db_key::ToDbKey, Error, Result};
     use bls::SecretKeySet;
     use bls::{PublicKeySet, SecretKey, SecretKeyShare};
+    use pickledb::{PickleDb, PickleDbDumpPolicy};
     use sn_data_types::{
         Credit, CreditAgreementProof, CreditId, PublicKey, ReplicaEvent, SignedCredit, Token,
         TransferPropagated,
     };
-    use std::collections::BTreeMap;
+    use std::{collections::BTreeMap, fs::OpenOptions, io::Write};
     use tempdir::TempDir;
 
     #[test]
@@ -114,7 +419,7 @@ mod test {
         let id = xor_name::XorName::random();
         let tmp_dir = TempDir::new("root")?;
         let root_dir = tmp_dir.into_path();
-        let mut store = TransferStore::new(id, &root_dir)?;
+        let mut store = TransferStore::new(id, &root_dir, None)?;
         let wallet_id = get_random_pk();
         let mut rng = rand::thread_rng();
         let bls_secret_key = SecretKeySet::random(0, &mut rng);
@@ -128,7 +433,7 @@ mod test {
             credit_proof: genesis_credit_proof.clone(),
         }))?;
 
-        let events = store.get_all();
+        let events = store.get_all()?;
         assert_eq!(events.len(), 1);
 
         match &events[0] {
@@ -146,6 +451,135 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn encrypted_history() -> Result<()> {
+        let id = xor_name::XorName::random();
+        let tmp_dir = TempDir::new("root")?;
+        let root_dir = tmp_dir.into_path();
+        let encryption = EncryptionKey::random();
+        let credit_proofs = get_credits(&[10, 20])?;
+
+        // An event stored before encryption was enabled, and one stored encrypted.
+        let mut store = TransferStore::new(id, &root_dir, None)?;
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[0].clone(),
+        }))?;
+        let mut store = TransferStore::new(id, &root_dir, Some(encryption.clone()))?;
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[1].clone(),
+        }))?;
+
+        let store = TransferStore::<ReplicaEvent>::new(id, &root_dir, Some(encryption))?;
+        let events = store.get_all()?;
+        assert_eq!(events.len(), 2);
+        for (event, expected) in events.iter().zip(&credit_proofs) {
+            match event {
+                ReplicaEvent::TransferPropagated(TransferPropagated { credit_proof, .. }) => {
+                    assert_eq!(credit_proof, expected)
+                }
+                other => {
+                    return Err(Error::Logic(format!(
+                        "Incorrect Replica event: {:?}",
+                        other
+                    )))
+                }
+            }
+        }
+
+        // Without the key, the history can't be read, rather than only part of it.
+        let store = TransferStore::<ReplicaEvent>::new(id, &root_dir, None)?;
+        assert!(store.get_all().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_pickle_db() -> Result<()> {
+        let id = xor_name::XorName::random();
+        let tmp_dir = TempDir::new("root")?;
+        let root_dir = tmp_dir.into_path();
+        let credit_proofs = get_credits(&[10, 20, 30])?;
+
+        // Keys are sorted numerically, not as strings.
+        let db_dir = root_dir.join(TRANSFERS_DIR_NAME);
+        std::fs::create_dir_all(&db_dir)?;
+        let db_path = db_dir.join(format!("{}{}", id.to_db_key()?, DB_EXTENSION));
+        let mut db = PickleDb::new_bin(&db_path, PickleDbDumpPolicy::DumpUponRequest);
+        for (key, credit_proof) in ["0", "1", "10"].iter().zip(&credit_proofs) {
+            db.set(
+                key,
+                &ReplicaEvent::TransferPropagated(TransferPropagated {
+                    credit_proof: credit_proof.clone(),
+                }),
+            )?;
+        }
+        db.dump()?;
+
+        let store = TransferStore::<ReplicaEvent>::new(id, &root_dir, None)?;
+        assert!(!db_path.exists());
+        assert_eq!(store.get_all()?.len(), 3);
+        for (event, expected) in store.get_all()?.iter().zip(&credit_proofs) {
+            match event {
+                ReplicaEvent::TransferPropagated(TransferPropagated { credit_proof, .. }) => {
+                    assert_eq!(credit_proof, expected)
+                }
+                other => {
+                    return Err(Error::Logic(format!(
+                        "Incorrect Replica event: {:?}",
+                        other
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncates_torn_record() -> Result<()> {
+        let id = xor_name::XorName::random();
+        let tmp_dir = TempDir::new("root")?;
+        let root_dir = tmp_dir.into_path();
+        let credit_proofs = get_credits(&[10, 20])?;
+
+        let mut store = TransferStore::new(id, &root_dir, None)?;
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[0].clone(),
+        }))?;
+        let log_len = std::fs::metadata(&store.log_path)?.len();
+
+        // Simulate a crash in the middle of appending a record.
+        let mut file = OpenOptions::new().append(true).open(&store.log_path)?;
+        file.write_all(&[16, 0, 0, 0, 1, 2, 3, 4, 5])?;
+        drop(store);
+
+        let mut store = TransferStore::new(id, &root_dir, None)?;
+        assert_eq!(std::fs::metadata(&store.log_path)?.len(), log_len);
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[1].clone(),
+        }))?;
+        assert_eq!(store.get_all()?.len(), 2);
+
+        Ok(())
+    }
+
+    fn get_credits(amounts: &[u64]) -> Result<Vec<CreditAgreementProof>> {
+        let wallet_id = get_random_pk();
+        let mut rng = rand::thread_rng();
+        let bls_secret_key = SecretKeySet::random(0, &mut rng);
+        amounts
+            .iter()
+            .map(|amount| {
+                get_credit(
+                    *amount,
+                    wallet_id,
+                    bls_secret_key.public_keys(),
+                    bls_secret_key.secret_key_share(0),
+                )
+            })
+            .collect()
+    }
+
     fn get_random_pk() -> PublicKey {
         PublicKey::from(SecretKey::random().public_key())
     }
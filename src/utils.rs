@@ -12,13 +12,17 @@ use crate::{config_handler::Config, Error, Result};
 use bytes::Bytes;
 use flexi_logger::{DeferredNow, Logger};
 use log::{Log, Metadata, Record};
-use pickledb::{PickleDb, PickleDbDumpPolicy};
 use rand::{distributions::Standard, CryptoRng, Rng};
 use serde::{de::DeserializeOwned, Serialize};
-use std::io::Write;
-use std::{fs, path::Path};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 const NODE_MODULE_NAME: &str = "sn_node";
+/// Suffix of the temp files `write_atomically` and the chunk store write to.
+pub(crate) const TEMP_FILE_SUFFIX: &str = ".tmp";
 
 /// Easily create a `BTreeSet`.
 #[macro_export]
@@ -56,25 +60,6 @@ macro_rules! btree_map {
     };
 }
 
-pub(crate) fn new_auto_dump_db<D: AsRef<Path>, N: AsRef<Path>>(
-    db_dir: D,
-    db_name: N,
-) -> Result<PickleDb> {
-    let db_path = db_dir.as_ref().join(db_name);
-    match PickleDb::load_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump) {
-        Ok(db) => Ok(db),
-        Err(_) => {
-            fs::create_dir_all(db_dir)?;
-            let mut db = PickleDb::new_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump);
-
-            // dump is needed to actually write the db to disk.
-            db.dump()?;
-
-            PickleDb::load_bin(db_path, PickleDbDumpPolicy::AutoDump).map_err(Error::PickleDb)
-        }
-    }
-}
-
 #[allow(dead_code)]
 pub(crate) fn random_vec<R: CryptoRng + Rng>(rng: &mut R, size: usize) -> Vec<u8> {
     rng.sample_iter(&Standard).take(size).collect()
@@ -85,6 +70,19 @@ pub(crate) fn serialise<T: Serialize>(data: &T) -> Result<Bytes> {
     Ok(Bytes::copy_from_slice(serialised_data.as_slice()))
 }
 
+/// Writes `contents` to a temp file next to `path`, and only renames it into place once it has been
+/// synced to disk, so that `path` never holds partially written contents.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(TEMP_FILE_SUFFIX);
+    let temp_path = PathBuf::from(temp_path);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
 #[allow(unused)]
 pub(crate) fn deserialise<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
     bincode::deserialize(bytes).map_err(Error::Bincode)